@@ -39,10 +39,10 @@ fn main() {
         .write_resource::<ExampleResource1>()
         .read_resource::<ExampleResource2>()
         .with_query(<(Write<Pos>, Read<Vel>)>::query())
-        .build(|_, mut world, (res1, res2), query| {
+        .build(|_, world, (res1, res2), query| {
             res1.0 = res2.0.clone(); // Write the mutable resource from the immutable resource
 
-            query.iter(&mut world).for_each(|(mut pos, vel)| {
+            query.iter(world).for_each(|(mut pos, vel)| {
                 pos.0 += vel.0;
                 pos.1 += vel.1;
                 pos.2 += vel.2;
@@ -51,9 +51,9 @@ fn main() {
 
     let print_system = SystemBuilder::new("print_system")
         .with_query(<Read<Pos>>::query())
-        .build(|_, mut world, _, query| {
+        .build(|_, world, _, query| {
             query
-                .iter(&mut world)
+                .iter(world)
                 .for_each(|pos| println!("Position {} {} {}", pos.0, pos.1, pos.2))
         });
 
@@ -1,6 +1,4 @@
-use crius::event::{EventChannel, KeyboardInput, VirtualKeyCode, WindowEvent};
 use crius::prelude::*;
-use winit::event::ElementState;
 
 pub struct MainScene;
 
@@ -11,67 +9,57 @@ pub enum MyCustomEvent {
 }
 
 impl Scene for MainScene {
-    fn stop(&mut self, context: Context) {
+    fn stop(&mut self, _context: Context) {
         println!("Stopping scene");
     }
 
-    fn handle_event(&mut self, context: Context, event: Event) -> Transition {
+    fn handle_event(&mut self, _context: Context, event: Event) -> Transition {
+        println!("EVENT: {:?}", event);
+        Transition::None
+    }
+
+    fn update(&mut self, context: Context) -> Transition {
         let Context { world, .. } = context;
-        match event {
-            Event::Application(event) => println!("EVENT: {:?}", event),
-            Event::Window(event) => {
-                println!("EVENT: {:?}", event);
-                match event {
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(key),
-                                modifiers,
-                                ..
-                            },
-                        ..
-                    } => match key {
-                        VirtualKeyCode::A if modifiers.shift() => {
-                            if let Some(channel) =
-                                world.resources.get_mut::<EventChannel<MyCustomEvent>>()
-                            {
-                                channel.write(MyCustomEvent::Foo).unwrap()
-                            }
-                        }
-                        VirtualKeyCode::S => {
-                            if let Some(channel) =
-                                world.resources.get_mut::<EventChannel<MyCustomEvent>>()
-                            {
-                                channel.write(MyCustomEvent::Bla).unwrap()
-                            }
-                        }
-                        VirtualKeyCode::Escape => return Transition::Quit,
-                        _ => {}
-                    },
-                    _ => {}
-                }
-            }
+        let actions = world.resources.get::<ActionState>().unwrap();
+        if actions.is_pressed("quit") {
+            return Transition::Quit;
+        }
+
+        let mut events = world.resources.get_mut::<Events<MyCustomEvent>>().unwrap();
+        if actions.is_pressed("foo") {
+            events.send(MyCustomEvent::Foo);
+        }
+        if actions.is_pressed("bla") {
+            events.send(MyCustomEvent::Bla);
         }
 
         Transition::None
     }
 }
 
-fn main() {
-    let mut channel = EventChannel::<MyCustomEvent>::default();
-    let reader = channel.bind_listener(128);
-    ApplicationBuilder::new(MainScene, "examples/playground")
-        .with_resource(channel)
-        .with_system("debug_system", move |_, system_builder| {
-            system_builder
-                .read_resource::<EventChannel<MyCustomEvent>>()
-                .build(move |_, _, user_event_channel, _| {
-                    while let Some(e) = user_event_channel.read(reader) {
-                        println!("debug_system received user event: {:?}", e)
-                    }
-                })
+fn main() -> crius::Result<()> {
+    let builder = ApplicationBuilder::new(MainScene, "examples/playground");
+
+    // Background threads hand events to the scenes through the event sender.
+    let sender = builder.event_sender();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        if sender.send(MyCustomEvent::Bla).is_err() {
+            break;
+        }
+    });
+
+    builder
+        .with_args(std::env::args())
+        .with_event::<MyCustomEvent>()
+        .with_system("debug_system", |_, system_builder| {
+            let (system_builder, mut reader) = system_builder.read_events::<MyCustomEvent>();
+            system_builder.build(move |_, _, events, _| {
+                for e in reader.read(events) {
+                    println!("debug_system received event: {:?}", e)
+                }
+            })
         })
-        .build()
+        .build()?
         .run()
 }
@@ -3,6 +3,7 @@ use crius::prelude::*;
 pub struct MainScene;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Position {
     x: f32,
     y: f32,
@@ -10,6 +11,7 @@ struct Position {
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 struct Velocity {
     x: f32,
     y: f32,
@@ -20,7 +22,7 @@ impl Scene for MainScene {
     fn start(&mut self, context: Context) {
         println!("Starting Scene!");
 
-        let Context { universe: _, world } = context;
+        let Context { world, .. } = context;
 
         world.insert(
             (),
@@ -48,8 +50,9 @@ struct Resource3 {
     pub b: String,
 }
 
-fn main() {
+fn main() -> crius::Result<()> {
     ApplicationBuilder::new(MainScene, "examples/playground")
+        .with_args(std::env::args())
         .with_resource(Resource3 {
             a: 30,
             b: "!!!".to_string(),
@@ -70,6 +73,6 @@ fn main() {
             system_builder.build_thread_local(|_, _, _, _| println!("Thread local system"))
         })
         .with_thread_local_fn(|_| println!("Thread local function!"))
-        .build()
+        .build()?
         .run()
 }
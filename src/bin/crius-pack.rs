@@ -0,0 +1,33 @@
+//! Packs a directory into a crius archive.
+//!
+//! ```text
+//! crius-pack <directory> <archive>
+//! ```
+
+use crius::vfs::ArchiveWriter;
+use std::env;
+use std::process;
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let (directory, archive) = match args.as_slice() {
+        [directory, archive] => (directory, archive),
+        _ => {
+            eprintln!("Usage: crius-pack <directory> <archive>");
+            process::exit(2);
+        }
+    };
+
+    let mut writer = ArchiveWriter::new();
+    let result = writer
+        .add_directory(directory)
+        .and_then(|_| writer.write(archive));
+
+    match result {
+        Ok(()) => println!("Packed {} files into {}", writer.len(), archive),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
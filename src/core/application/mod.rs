@@ -4,181 +4,895 @@
 
 pub mod settings;
 
-use crate::core::application::settings::Settings;
-use crate::core::event::ApplicationEvent;
+use crate::core::application::settings::{
+    find_settings_file, HeadlessSettings, Settings, SettingsLoader, WindowSettings,
+};
+use crate::core::asset::{AssetLoader, AssetLoaders, AssetServer};
+use crate::core::error::{Error, Result};
+use crate::core::event::{
+    ApplicationEvent, EventChannel, EventRegistry, EventSender, Events, UserEvent, WindowId,
+};
+#[cfg(feature = "gilrs")]
+use crate::core::input::GilrsGamepads;
+use crate::core::input::{ActionState, Bindings, GamepadBackend, GamepadState, InputState};
+use crate::core::pacing::FramePacing;
+use crate::core::plugin::{self, PendingPlugin, Plugin};
+use crate::core::prefab::Prefabs;
+use crate::core::registry::ComponentRegistry;
+use crate::core::replay::{RecordedEvent, Recorder, Replay};
 use crate::core::scene::{Context, Scene, SceneManager, Transition};
-use crate::prelude::{Event, Schedule};
-use legion::schedule::{Builder, Runnable, Schedulable};
+use crate::core::schedule::{Stage, StageSchedules, StageSteps, Stages};
+use crate::core::serialization::checksum;
+use crate::core::time::Time;
+use crate::core::timestep::FixedTimestep;
+use crate::core::vfs::Vfs;
+use crate::core::watcher::{FileChanged, FileWatcher, SettingsError};
+use crate::core::window::{
+    self as window_control, WindowCommand, WindowCommands, WindowError, Windows,
+};
+use crate::prelude::Event;
+use legion::schedule::{Runnable, Schedulable};
+use legion::storage::Component;
 use legion::system::SystemBuilder;
 use legion::world::{Universe, World};
-use serde_yaml;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::TypeId;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use winit::dpi::PhysicalSize;
 use winit::event::Event as WinitEvent;
 use winit::event::WindowEvent as WinitWindowEvent;
-use winit::event_loop::ControlFlow;
-use winit::window::{Window, WindowBuilder};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use winit::platform::desktop::EventLoopExtDesktop;
+use winit::window::Window;
 
-const APPLICATION_SETTINGS_FILE_NAME: &str = "settings.yml";
+const APPLICATION_SETTINGS_FILE_STEM: &str = "settings";
+const SETTINGS_ENVIRONMENT_PREFIX: &str = "CRIUS";
+const BINDINGS_FILE_STEM: &str = "bindings";
 
 pub struct Application {
     universe: Universe,
     world: World,
     scene_manager: SceneManager,
-    schedule: Schedule,
+    stages: Stages,
+    schedules: StageSchedules,
     settings: Settings,
+    settings_loader: Option<SettingsLoader>,
+    assets_path: PathBuf,
+    watcher: Option<FileWatcher>,
+    gamepad_backend: Option<Box<dyn GamepadBackend>>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    event_sender: EventSender,
+    headless: Option<HeadlessSettings>,
+    last_frame: Instant,
+    /// The error a scene failed to start with, returned once the application has stopped.
+    scene_error: Option<Error>,
 }
 
 impl Application {
-    pub fn run(self) {
-        let Application {
-            mut universe,
-            mut world,
-            mut scene_manager,
-            mut schedule,
-            settings,
-        } = self;
-
-        scene_manager.initialize(Context::new(&mut universe, &mut world));
-
-        let event_loop = winit::event_loop::EventLoop::new();
-
-        let mut window_builder = WindowBuilder::new()
-            .with_title(settings.window.title)
-            .with_resizable(settings.window.resizeable)
-            .with_maximized(settings.window.maximized)
-            .with_visible(settings.window.visible)
-            .with_transparent(settings.window.transparent)
-            .with_decorations(settings.window.decorations)
-            .with_always_on_top(settings.window.always_on_top);
-
-        if let Some(size) = settings.window.size {
-            window_builder = window_builder.with_inner_size(size);
+    /// Runs the application until the scene stack is empty or a scene quits.
+    ///
+    /// Applications built with [`ApplicationBuilder::headless`], or whose settings
+    /// do not describe a window, run without a window or an event loop.
+    ///
+    /// Applications built with [`ApplicationBuilder::replay`] run the recorded frames without
+    /// a window, and fail with `Error::Diverged` if a frame ends with a different checksum.
+    /// Applications built with [`ApplicationBuilder::record`] fail if the recording cannot be
+    /// written.
+    ///
+    /// Applications stop with `Error::Stage` when a scene adds systems to a custom stage that
+    /// was not added.
+    ///
+    /// [`ApplicationBuilder::headless`]: struct.ApplicationBuilder.html#method.headless
+    /// [`ApplicationBuilder::replay`]: struct.ApplicationBuilder.html#method.replay
+    /// [`ApplicationBuilder::record`]: struct.ApplicationBuilder.html#method.record
+    pub fn run(mut self) -> Result<()> {
+        if let Some(replay) = self.replay.take() {
+            return self.run_replay(replay);
         }
 
-        if let Some(min) = settings.window.min_size {
-            window_builder = window_builder.with_max_inner_size(min);
+        if self.headless.is_none() && self.settings.all_windows().next().is_some() {
+            return self.run_windowed();
         }
 
-        if let Some(max) = settings.window.max_size {
-            window_builder = window_builder.with_max_inner_size(max);
+        let headless = self.headless.unwrap_or_default();
+        self.run_headless(headless)
+    }
+
+    fn run_windowed(mut self) -> Result<()> {
+        let mut event_loop = EventLoop::<UserEvent>::with_user_event();
+
+        let mut windows = Windows::default();
+        for settings in self.settings.all_windows() {
+            let window = window_control::build_window(settings, &event_loop)?;
+            windows.insert(settings.name.clone(), window);
         }
+        self.world.resources.insert(windows);
 
-        let window = window_builder.build(&event_loop).unwrap();
+        self.start();
+        self.event_sender.start(event_loop.create_proxy());
 
-        world.resources.insert(window);
+        // Input is only cleared once a frame has used it, since capped frame rates leave
+        // some iterations of the event loop without a frame.
+        let mut frame_ran = true;
+        // Scenes draw once per frame, however many windows are redrawn.
+        let mut draw_pending = false;
+        let mut next_frame = ControlFlow::Poll;
+        let mut result = Ok(());
 
-        event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
+        // The loop returns so that errors met while it runs can be returned.
+        event_loop.run_return(|event, target, control_flow| {
+            *control_flow = next_frame;
 
             match event {
                 WinitEvent::WindowEvent {
+                    window_id,
                     event: WinitWindowEvent::CloseRequested,
-                    ..
-                } => *control_flow = ControlFlow::Exit,
-                WinitEvent::WindowEvent { event, .. } => {
-                    let transition = scene_manager
-                        .handle_event(Context::new(&universe, &mut world), Event::Window(event));
-                    Self::handle_transition(
-                        &mut scene_manager,
-                        transition,
-                        Context::new(&universe, &mut world),
-                        control_flow,
-                    )
+                } => {
+                    let id = self.window_id(window_id);
+                    if id.is_some_and(|id| self.close_window(id)) {
+                        *control_flow = ControlFlow::Exit
+                    }
+                }
+                WinitEvent::NewEvents(_) => {
+                    if frame_ran {
+                        self.end_input_frame();
+                        frame_ran = false;
+                    }
+                }
+                WinitEvent::WindowEvent { window_id, event } => {
+                    // Windows that were just closed can still send events.
+                    if let Some(id) = self.window_id(window_id) {
+                        let transition = self.dispatch_event(Event::Window(id, event));
+                        if self.handle_transition(transition) {
+                            *control_flow = ControlFlow::Exit
+                        }
+                    }
+                }
+                WinitEvent::DeviceEvent { event, .. } => {
+                    let transition = self.dispatch_event(Event::Device(event));
+                    if self.handle_transition(transition) {
+                        *control_flow = ControlFlow::Exit
+                    }
+                }
+                WinitEvent::UserEvent(event) => {
+                    let transition = self.dispatch_event(Event::User(event));
+                    if self.handle_transition(transition) {
+                        *control_flow = ControlFlow::Exit
+                    }
                 }
                 WinitEvent::Suspended => {
-                    let transition = scene_manager.handle_event(
-                        Context::new(&universe, &mut world),
-                        Event::Application(ApplicationEvent::Suspended),
-                    );
-                    scene_manager.pause(Context::new(&mut universe, &mut world));
                     *control_flow = ControlFlow::Wait;
-                    Self::handle_transition(
-                        &mut scene_manager,
-                        transition,
-                        Context::new(&universe, &mut world),
-                        control_flow,
-                    );
+                    if self.suspend() {
+                        *control_flow = ControlFlow::Exit
+                    }
                 }
                 WinitEvent::Resumed => {
-                    let transition = scene_manager.handle_event(
-                        Context::new(&universe, &mut world),
-                        Event::Application(ApplicationEvent::Resumed),
-                    );
-                    scene_manager.resume(Context::new(&universe, &mut world));
                     *control_flow = ControlFlow::Poll;
-                    Self::handle_transition(
-                        &mut scene_manager,
-                        transition,
-                        Context::new(&universe, &mut world),
-                        control_flow,
-                    )
+                    if self.resume() {
+                        *control_flow = ControlFlow::Exit
+                    }
                 }
                 WinitEvent::MainEventsCleared => {
-                    let transition = scene_manager.update(Context::new(&mut universe, &mut world));
-                    Self::handle_transition(
-                        &mut scene_manager,
-                        transition,
-                        Context::new(&universe, &mut world),
-                        control_flow,
-                    );
-                    world.resources.get::<Window>().unwrap().request_redraw()
+                    if self.is_frame_due() {
+                        frame_ran = true;
+                        draw_pending = true;
+                        let delta = self.elapsed_since_last_frame();
+                        if self.poll_gamepads() || self.update(delta) {
+                            *control_flow = ControlFlow::Exit
+                        }
+                        if let Some(windows) = self.world.resources.get::<Windows>() {
+                            for window in windows.ids().filter_map(|id| windows.get(id)) {
+                                window.request_redraw();
+                            }
+                        }
+                    }
+
+                    next_frame = self.next_frame_control_flow();
+                    if *control_flow != ControlFlow::Exit {
+                        *control_flow = next_frame;
+                    }
                 }
                 WinitEvent::RedrawRequested(_) => {
-                    schedule.execute(&mut world);
+                    if draw_pending {
+                        draw_pending = false;
+                        if self.draw() {
+                            *control_flow = ControlFlow::Exit
+                        }
+                    }
+                }
+                WinitEvent::RedrawEventsCleared => {
+                    if let Err(error) = self.record_frame() {
+                        result = Err(error);
+                        *control_flow = ControlFlow::Exit
+                    }
                 }
                 WinitEvent::LoopDestroyed => {
                     // Event loop is being destroyed, no more transitions will be handled.
-                    scene_manager.handle_event(
-                        Context::new(&universe, &mut world),
-                        Event::Application(ApplicationEvent::Terminating),
-                    );
-                    scene_manager.stop(Context::new(&mut universe, &mut world))
+                    let stopped = self.stop();
+                    result = mem::replace(&mut result, Ok(())).and(stopped);
                 }
-                _ => {}
             }
-        })
+
+            if self.apply_window_commands(Some(target)) {
+                *control_flow = ControlFlow::Exit
+            }
+        });
+        result
+    }
+
+    /// The id the scenes know the window winit reports as `id` by.
+    fn window_id(&self, id: winit::window::WindowId) -> Option<WindowId> {
+        self.world
+            .resources
+            .get::<Windows>()
+            .and_then(|windows| windows.id_of_winit(id))
+    }
+
+    /// Closes the window `id`. Returns `true` if it was the primary window, in which case
+    /// the application should exit.
+    fn close_window(&mut self, id: WindowId) -> bool {
+        match self.world.resources.get_mut::<Windows>() {
+            Some(mut windows) if windows.primary_id() != Some(id) => {
+                windows.remove(id);
+            }
+            Some(_) => return true,
+            None => return false,
+        }
+        if let Some(mut pacing) = self.world.resources.get_mut::<FramePacing>() {
+            pacing.remove_window(id);
+        }
+        false
+    }
+
+    /// Applies the window changes queued by the scenes and systems. They are dropped when
+    /// there is no window. Windows are only opened when `target` is set. Failures are
+    /// written to the `EventChannel<WindowError>` resource.
+    /// Returns `true` if the application should exit.
+    fn apply_window_commands(&mut self, target: Option<&EventLoopWindowTarget<UserEvent>>) -> bool {
+        let commands = match self.world.resources.get_mut::<WindowCommands>() {
+            Some(mut commands) => commands.drain(),
+            None => return false,
+        };
+
+        for command in commands {
+            if let WindowCommand::Close(id) = command {
+                if self.close_window(id) {
+                    return true;
+                }
+                continue;
+            }
+
+            let mut windows = match self.world.resources.get_mut::<Windows>() {
+                Some(windows) => windows,
+                None => return false,
+            };
+            let error = match (command, target) {
+                (WindowCommand::Open(settings), Some(target)) => {
+                    open_window(&mut windows, &settings, target)
+                        .err()
+                        .map(|error| WindowError {
+                            command: WindowCommand::Open(settings),
+                            message: error.to_string(),
+                        })
+                }
+                (command, _) => window_control::apply(&mut windows, command),
+            };
+            if let (Some(error), Some(channel)) = (
+                error,
+                self.world.resources.get::<EventChannel<WindowError>>(),
+            ) {
+                let _ = channel.write(error);
+            }
+        }
+        false
+    }
+
+    fn run_headless(mut self, headless: HeadlessSettings) -> Result<()> {
+        let frame_duration = headless
+            .tick_rate
+            .filter(|&tick_rate| tick_rate > 0)
+            .map(|tick_rate| Duration::from_secs_f64(1.0 / f64::from(tick_rate)));
+
+        self.insert_placeholder_windows();
+        self.start();
+
+        let mut frame_count = 0;
+        let mut result = Ok(());
+
+        while self.scene_manager.is_running()
+            && headless
+                .max_frames
+                .is_none_or(|max_frames| frame_count < max_frames)
+        {
+            let frame_start = Instant::now();
+
+            self.end_input_frame();
+            let delta = self.elapsed_since_last_frame();
+            if self.poll_gamepads()
+                || self.deliver_queued_events()
+                || self.update(delta)
+                || self.draw()
+            {
+                break;
+            }
+            if let Err(error) = self.record_frame() {
+                result = Err(error);
+                break;
+            }
+            if self.apply_window_commands(None) {
+                break;
+            }
+
+            frame_count += 1;
+
+            if let Some(frame_duration) = frame_duration {
+                let elapsed = frame_start.elapsed();
+                if elapsed < frame_duration {
+                    thread::sleep(frame_duration - elapsed)
+                }
+            }
+        }
+
+        let stopped = self.stop();
+        result.and(stopped)
     }
 
-    fn handle_transition(
-        scene_manager: &mut SceneManager,
-        transition: Transition,
-        context: Context,
-        control_flow: &mut ControlFlow,
-    ) {
-        let Context { universe, world } = context;
+    /// Runs the frames of `replay` with their recorded events and durations.
+    fn run_replay(mut self, replay: Replay) -> Result<()> {
+        self.insert_placeholder_windows();
+        self.start();
 
-        match transition {
-            Transition::Push(scene) => scene_manager.push(scene, Context::new(universe, world)),
-            Transition::Switch(_) => {}
-            Transition::Pop => {}
-            Transition::Quit => {
-                *control_flow = ControlFlow::Exit;
+        let mut result = Ok(());
+        for (index, frame) in replay.frames.iter().enumerate() {
+            if !self.scene_manager.is_running() {
+                break;
+            }
+
+            self.end_input_frame();
+            if self.replay_events(&frame.events)
+                || self.deliver_queued_events()
+                || self.update(frame.delta)
+                || self.draw()
+            {
+                break;
+            }
+            if let Err(error) = self.record_frame() {
+                result = Err(error);
+                break;
             }
-            _ => {}
+
+            if let Some(expected) = frame.checksum {
+                let actual = self.checksum(&replay.components);
+                if actual != expected {
+                    result = Err(Error::Diverged {
+                        frame: index,
+                        expected,
+                        actual,
+                    });
+                    break;
+                }
+            }
+            if self.apply_window_commands(None) {
+                break;
+            }
+        }
+
+        let stopped = self.stop();
+        result.and(stopped)
+    }
+
+    /// Adds the windows of the settings to the `Windows` resource without opening them, so
+    /// that runs without an event loop give them the ids they get when opened.
+    fn insert_placeholder_windows(&mut self) {
+        let mut windows = Windows::default();
+        for settings in self.settings.all_windows() {
+            windows.insert_placeholder(settings.name.clone());
         }
+        self.world.resources.insert(windows);
     }
+
+    /// Hands recorded events to the scenes. Returns `true` if the application should exit.
+    fn replay_events(&mut self, events: &[RecordedEvent]) -> bool {
+        let mut new_inner_size = PhysicalSize::new(0, 0);
+        for event in events {
+            let exit = match event {
+                RecordedEvent::Application(ApplicationEvent::Suspended) => self.suspend(),
+                RecordedEvent::Application(ApplicationEvent::Resumed) => self.resume(),
+                event => {
+                    let event = match self.world.resources.get_mut::<Windows>() {
+                        Some(mut windows) => event.to_event(&mut windows, &mut new_inner_size),
+                        None => continue,
+                    };
+                    let transition = self.dispatch_event(event);
+                    self.handle_transition(transition)
+                }
+            };
+            if exit {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn start(&mut self) {
+        let time_settings = self.settings.time;
+        let fixed_delta = Duration::from_secs_f64(time_settings.fixed_timestep);
+        self.world.resources.insert(Time::new(fixed_delta));
+        self.world.resources.insert(FixedTimestep::new(
+            fixed_delta,
+            time_settings.max_fixed_steps,
+        ));
+
+        self.scene_error = self
+            .scene_manager
+            .initialize(Context::new(&self.universe, &mut self.world))
+            .err();
+        self.last_frame = Instant::now();
+    }
+
+    /// Clears the input of the previous frame before the events of the next one arrive.
+    fn end_input_frame(&mut self) {
+        if let Some(mut input) = self.world.resources.get_mut::<InputState>() {
+            input.end_frame();
+        }
+        if let Some(mut gamepads) = self.world.resources.get_mut::<GamepadState>() {
+            gamepads.end_frame();
+        }
+    }
+
+    /// Updates the input resources from `event`, records it and hands it to the scenes.
+    fn dispatch_event(&mut self, event: Event) -> Transition {
+        match &event {
+            Event::Window(id, event) => {
+                if let Some(mut input) = self.world.resources.get_mut::<InputState>() {
+                    input.handle_window_event(event);
+                }
+                if let Some(mut pacing) = self.world.resources.get_mut::<FramePacing>() {
+                    pacing.handle_window_event(*id, event);
+                }
+            }
+            Event::Device(event) => {
+                if let Some(mut input) = self.world.resources.get_mut::<InputState>() {
+                    input.handle_device_event(event);
+                }
+            }
+            Event::Gamepad(event) => {
+                if let Some(mut gamepads) = self.world.resources.get_mut::<GamepadState>() {
+                    gamepads.handle_event(event);
+                }
+            }
+            Event::Application(_) | Event::User(_) => {}
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            let windows = self.world.resources.get::<Windows>();
+            recorder.record(&event, windows.as_deref());
+        }
+
+        self.scene_manager
+            .handle_event(Context::new(&self.universe, &mut self.world), event)
+    }
+
+    /// Returns `true` if the application should exit.
+    fn suspend(&mut self) -> bool {
+        let transition = self.dispatch_event(Event::Application(ApplicationEvent::Suspended));
+        self.scene_manager
+            .pause(Context::new(&self.universe, &mut self.world));
+        self.handle_transition(transition)
+    }
+
+    /// Returns `true` if the application should exit.
+    fn resume(&mut self) -> bool {
+        let transition = self.dispatch_event(Event::Application(ApplicationEvent::Resumed));
+        self.scene_manager
+            .resume(Context::new(&self.universe, &mut self.world));
+        // Time spent suspended does not count towards the next frame.
+        self.last_frame = Instant::now();
+        self.handle_transition(transition)
+    }
+
+    /// Hands the user events sent while running without an event loop to the scenes.
+    /// Returns `true` if the application should exit.
+    fn deliver_queued_events(&mut self) -> bool {
+        for event in self.event_sender.take_queued() {
+            let transition = self.dispatch_event(Event::User(event));
+            if self.handle_transition(transition) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Hands the events of the gamepad backend to the `GamepadState` and then to the scenes.
+    /// Returns `true` if the application should exit.
+    fn poll_gamepads(&mut self) -> bool {
+        let events = match &mut self.gamepad_backend {
+            Some(backend) => backend.poll(),
+            None => return false,
+        };
+
+        for event in events {
+            let transition = self.dispatch_event(Event::Gamepad(event));
+            if self.handle_transition(transition) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn update_actions(&mut self) {
+        let resources = &self.world.resources;
+        if let (Some(mut actions), Some(input)) = (
+            resources.get_mut::<ActionState>(),
+            resources.get::<InputState>(),
+        ) {
+            actions.update(&input);
+        }
+    }
+
+    /// Starts a new frame for the registered event types.
+    fn update_events(&mut self) {
+        if let Some(registry) = self.world.resources.get::<EventRegistry>() {
+            registry.update(&self.world.resources);
+        }
+    }
+
+    /// Reloads the settings when one of their files changed, and reports the changes under
+    /// the assets directory to the `EventChannel<FileChanged>` resource.
+    fn poll_watcher(&mut self) {
+        let changes = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return,
+        };
+
+        let mut settings_changed = false;
+        let mut bindings_changed = false;
+        for change in changes {
+            let is_settings_file = self
+                .settings_loader
+                .as_ref()
+                .is_some_and(|loader| loader.files().any(|file| file == change.path));
+
+            let is_bindings_file = self
+                .world
+                .resources
+                .get::<ActionState>()
+                .is_some_and(|actions| actions.path() == change.path);
+
+            if is_settings_file {
+                settings_changed = true;
+            } else if is_bindings_file {
+                bindings_changed = true;
+            } else if let Ok(path) = change.path.strip_prefix(&self.assets_path) {
+                if let Some(channel) = self.world.resources.get::<EventChannel<FileChanged>>() {
+                    let _ = channel.write(FileChanged {
+                        path: path.to_path_buf(),
+                        kind: change.kind,
+                    });
+                }
+            }
+        }
+
+        if settings_changed {
+            self.reload_settings();
+        }
+        if bindings_changed {
+            self.reload_bindings();
+        }
+    }
+
+    /// Replaces the bindings of the `ActionState` with the ones in its file. Failures are
+    /// kept in `ActionState::last_reload_error`.
+    fn reload_bindings(&mut self) {
+        if let Some(mut actions) = self.world.resources.get_mut::<ActionState>() {
+            actions.reload();
+        }
+    }
+
+    /// Reloads the settings and applies the window settings that can change while the
+    /// windows are open. Failures are written to the `EventChannel<SettingsError>`
+    /// resource. Other settings, and windows added to them, only take effect the next time
+    /// the application runs.
+    fn reload_settings(&mut self) {
+        let settings = match self.settings_loader.as_ref().map(SettingsLoader::load) {
+            Some(Ok(settings)) => settings,
+            Some(Err(error)) => {
+                // Keep running with the current settings until the files are fixed.
+                if let Some(channel) = self.world.resources.get::<EventChannel<SettingsError>>() {
+                    let _ = channel.write(SettingsError {
+                        error: Arc::new(error),
+                    });
+                }
+                return;
+            }
+            None => return,
+        };
+
+        if let Some(mut windows) = self.world.resources.get_mut::<Windows>() {
+            for new in settings.all_windows() {
+                let current = self
+                    .settings
+                    .all_windows()
+                    .find(|current| current.name == new.name);
+                if let (Some(current), Some(id)) = (current, windows.id_of(&new.name)) {
+                    if let Some(window) = windows.get(id) {
+                        apply_window_settings(window, current, new);
+                    }
+                    if new.fullscreen != current.fullscreen {
+                        windows.set_fullscreen(id, &new.fullscreen);
+                    }
+                }
+            }
+        }
+
+        // Changes made at runtime are kept unless the files change the frame pacing too.
+        if settings.frame_pacing != self.settings.frame_pacing {
+            if let Some(mut pacing) = self.world.resources.get_mut::<FramePacing>() {
+                // Reloaded settings are validated already.
+                let _ = pacing.set_settings(settings.frame_pacing);
+            }
+        }
+
+        self.settings = settings;
+    }
+
+    /// Runs the fixed updates accumulated since the last frame followed by the frame update.
+    /// Returns `true` if the application should exit.
+    fn update(&mut self, unscaled_delta: Duration) -> bool {
+        self.poll_watcher();
+        self.update_actions();
+        self.update_events();
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.set_delta(unscaled_delta);
+        }
+
+        let frame_delta = match self.world.resources.get_mut::<Time>() {
+            Some(mut time) => {
+                time.advance(unscaled_delta);
+                time.delta()
+            }
+            None => unscaled_delta,
+        };
+
+        self.execute_stages(Stage::First);
+
+        let fixed_steps = self
+            .world
+            .resources
+            .get_mut::<FixedTimestep>()
+            .map_or(0, |mut timestep| timestep.advance(frame_delta));
+
+        for _ in 0..fixed_steps {
+            let transition = self
+                .scene_manager
+                .fixed_update(Context::new(&self.universe, &mut self.world));
+            if self.handle_transition(transition) {
+                return true;
+            }
+        }
+
+        self.execute_stages(Stage::PreUpdate);
+        let transition = self
+            .scene_manager
+            .update(Context::new(&self.universe, &mut self.world));
+        if self.handle_transition(transition) {
+            return true;
+        }
+
+        self.execute_stages(Stage::Update);
+        self.execute_stages(Stage::PostUpdate);
+        let transition = self
+            .scene_manager
+            .late_update(Context::new(&self.universe, &mut self.world));
+        self.handle_transition(transition)
+    }
+
+    /// Executes the render stages around the draw callbacks.
+    /// Returns `true` if the application should exit.
+    fn draw(&mut self) -> bool {
+        self.execute_stages(Stage::PreRender);
+        self.execute_stages(Stage::Render);
+        self.scene_manager
+            .draw(Context::new(&self.universe, &mut self.world));
+        self.execute_stages(Stage::Last);
+        false
+    }
+
+    /// Executes the built-in stage `point` and the custom stages placed next to it.
+    fn execute_stages(&mut self, point: Stage) {
+        for stage in self.stages.at(point) {
+            self.scene_manager
+                .execute(&mut self.world, stage, &mut self.schedules);
+        }
+    }
+
+    fn is_frame_due(&self) -> bool {
+        self.world
+            .resources
+            .get::<FramePacing>()
+            .is_none_or(|pacing| pacing.is_frame_due(self.last_frame, Instant::now()))
+    }
+
+    /// How the event loop waits for the next frame.
+    fn next_frame_control_flow(&self) -> ControlFlow {
+        self.world
+            .resources
+            .get::<FramePacing>()
+            .map_or(ControlFlow::Poll, |pacing| {
+                pacing.control_flow(self.last_frame)
+            })
+    }
+
+    fn elapsed_since_last_frame(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.last_frame;
+        self.last_frame = now;
+        elapsed
+    }
+
+    /// Completes the frame being recorded, with a checksum of the world as the frame left it.
+    fn record_frame(&mut self) -> Result<()> {
+        let components = match &self.recorder {
+            Some(recorder) => recorder.components().to_vec(),
+            None => return Ok(()),
+        };
+
+        let checksum = if components.is_empty() {
+            None
+        } else {
+            Some(self.checksum(&components))
+        };
+        match &mut self.recorder {
+            Some(recorder) => recorder.end_frame(checksum),
+            None => Ok(()),
+        }
+    }
+
+    fn checksum(&self, components: &[String]) -> u32 {
+        self.world
+            .resources
+            .get::<ComponentRegistry>()
+            .map_or(0, |registry| checksum(&registry, &self.world, components))
+    }
+
+    /// Stops the scenes and writes the rest of the recording. Fails with the error a scene
+    /// failed to start with, if any.
+    fn stop(&mut self) -> Result<()> {
+        self.event_sender.stop();
+        self.dispatch_event(Event::Application(ApplicationEvent::Terminating));
+        self.scene_manager
+            .stop(Context::new(&self.universe, &mut self.world));
+
+        let saved = match &mut self.recorder {
+            Some(recorder) => recorder.save(),
+            None => Ok(()),
+        };
+        match self.scene_error.take() {
+            Some(error) => Err(error),
+            None => saved,
+        }
+    }
+
+    /// Applies `transition` to the scene stack.
+    /// Returns `true` if the application should exit, which it does when a scene fails to
+    /// start.
+    fn handle_transition(&mut self, transition: Transition) -> bool {
+        let context = Context::new(&self.universe, &mut self.world);
+
+        let result = match transition {
+            Transition::Push(scene) => self.scene_manager.push(scene, context),
+            Transition::Switch(scene) => self.scene_manager.switch(scene, context),
+            Transition::Pop => {
+                self.scene_manager.pop(context);
+                Ok(())
+            }
+            Transition::Quit => return true,
+            Transition::None => Ok(()),
+        };
+        if let Err(error) = result {
+            self.scene_error = Some(error);
+            return true;
+        }
+
+        !self.scene_manager.is_running()
+    }
+}
+
+/// Opens the window described by `settings` and adds it to `windows`.
+fn open_window(
+    windows: &mut Windows,
+    settings: &WindowSettings,
+    target: &EventLoopWindowTarget<UserEvent>,
+) -> Result<()> {
+    settings.validate()?;
+    if windows.id_of(&settings.name).is_some() {
+        return Err(Error::DuplicateWindow {
+            name: settings.name.clone(),
+        });
+    }
+
+    let window = window_control::build_window(settings, target)?;
+    windows.insert(settings.name.clone(), window);
+    Ok(())
 }
 
-pub struct ApplicationBuilder<P>
-where
-    P: AsRef<Path>,
-{
+fn apply_window_settings(window: &Window, current: &WindowSettings, new: &WindowSettings) {
+    if new.title != current.title {
+        window.set_title(&new.title);
+    }
+
+    if new.size != current.size {
+        if let Some(size) = new.size {
+            window.set_inner_size(size);
+        }
+    }
+
+    if new.decorations != current.decorations {
+        window.set_decorations(new.decorations);
+    }
+
+    if new.always_on_top != current.always_on_top {
+        window.set_always_on_top(new.always_on_top);
+    }
+
+    if new.position != current.position {
+        if let Some(position) = new.position {
+            window.set_outer_position(position);
+        }
+    }
+}
+
+#[cfg(feature = "gilrs")]
+fn default_gamepad_backend() -> Option<Box<dyn GamepadBackend>> {
+    GilrsGamepads::new().map(|gamepads| Box::new(gamepads) as Box<dyn GamepadBackend>)
+}
+
+#[cfg(not(feature = "gilrs"))]
+fn default_gamepad_backend() -> Option<Box<dyn GamepadBackend>> {
+    None
+}
+
+pub struct ApplicationBuilder {
     universe: Universe,
     world: World,
     scene_manager: SceneManager,
-    schedule_builder: Builder,
-    working_directory: P,
+    stages: Stages,
+    stage_error: Option<Error>,
+    component_error: Option<Error>,
+    steps: StageSteps,
+    events: EventRegistry,
+    registry: ComponentRegistry,
+    asset_loaders: AssetLoaders,
+    working_directory: PathBuf,
+    settings: Option<Settings>,
+    user_settings: Option<PathBuf>,
+    settings_args: Vec<String>,
+    bindings: Bindings,
+    gamepad_backend: Option<Box<dyn GamepadBackend>>,
+    record_path: Option<PathBuf>,
+    replay_path: Option<PathBuf>,
+    checksum_components: Vec<String>,
+    event_sender: EventSender,
+    plugins: Vec<PendingPlugin>,
+    /// Every plugin added so far, built or not.
+    plugin_ids: Vec<TypeId>,
+    duplicate_plugin: Option<&'static str>,
+    headless: Option<HeadlessSettings>,
 }
 
-impl<P> ApplicationBuilder<P>
-where
-    P: AsRef<Path>,
-{
-    pub fn new<S>(initial_scene: S, working_directory: P) -> Self
+impl ApplicationBuilder {
+    pub fn new<S, P>(initial_scene: S, working_directory: P) -> Self
     where
         S: Scene + 'static,
+        P: AsRef<Path>,
     {
         let universe = Universe::new();
         let world = universe.create_world();
@@ -186,9 +900,183 @@ where
             universe,
             world,
             scene_manager: SceneManager::new(initial_scene),
-            schedule_builder: Schedule::builder(),
-            working_directory,
+            stages: Stages::default(),
+            stage_error: None,
+            component_error: None,
+            steps: StageSteps::default(),
+            events: EventRegistry::default(),
+            registry: ComponentRegistry::new(),
+            asset_loaders: AssetLoaders::default(),
+            working_directory: working_directory.as_ref().to_path_buf(),
+            settings: None,
+            user_settings: None,
+            settings_args: vec![],
+            bindings: Bindings::default(),
+            gamepad_backend: None,
+            record_path: None,
+            replay_path: None,
+            checksum_components: vec![],
+            event_sender: EventSender::new(),
+            plugins: Vec::new(),
+            plugin_ids: Vec::new(),
+            duplicate_plugin: None,
+            headless: None,
+        }
+    }
+
+    /// Uses `settings` instead of loading them from the working directory.
+    /// No other settings layer is applied.
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Layers the per-user settings file at `path`, if it exists, over the project settings.
+    pub fn with_user_settings<U>(mut self, path: U) -> Self
+    where
+        U: AsRef<Path>,
+    {
+        self.user_settings = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Applies the `--set key=value` overrides found in `args`, e.g. `std::env::args()`,
+    /// over every other settings layer.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.settings_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Runs the application without a window or an event loop.
+    /// Scenes and systems are driven by a plain loop configured by `settings`.
+    pub fn headless(mut self, settings: HeadlessSettings) -> Self {
+        self.headless = Some(settings);
+        self
+    }
+
+    /// Gives every scene an empty world of its own instead of the application's world.
+    /// See [`SceneManager::with_world_per_scene`](../scene/struct.SceneManager.html#method.with_world_per_scene).
+    pub fn with_world_per_scene(mut self) -> Self {
+        self.scene_manager = self.scene_manager.with_world_per_scene(true);
+        self
+    }
+
+    /// Registers `T` in the application's `ComponentRegistry`, so that entities can be
+    /// moved and cloned between worlds along with their `T` component.
+    pub fn with_component<T>(mut self) -> Self
+    where
+        T: Component + Clone,
+    {
+        self.registry.register::<T>();
+        self
+    }
+
+    /// Registers `T` under `name` in the application's `ComponentRegistry`, so that it is
+    /// saved and loaded along with entities by the [`serialization`] module.
+    ///
+    /// Building fails if `name` is already taken by another type.
+    ///
+    /// [`serialization`]: ../serialization/index.html
+    pub fn with_serializable_component<T>(mut self, name: &str) -> Self
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        let result = self.registry.register_serializable::<T>(name);
+        if self.component_error.is_none() {
+            self.component_error = result.err();
+        }
+        self
+    }
+
+    /// Adds `loader` to the application's `AssetServer`, replacing the loaders previously
+    /// added for the same extensions.
+    pub fn with_asset_loader<L>(mut self, loader: L) -> Self
+    where
+        L: AssetLoader,
+    {
+        self.asset_loaders.add(loader);
+        self
+    }
+
+    /// Uses `bindings` when the working directory has no bindings file.
+    pub fn with_bindings(mut self, bindings: Bindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Reads gamepads from `backend` instead of the default backend. Without the `gilrs`
+    /// feature there is no default backend, and no gamepad is ever connected.
+    pub fn with_gamepad_backend<B>(mut self, backend: B) -> Self
+    where
+        B: GamepadBackend,
+    {
+        self.gamepad_backend = Some(Box::new(backend));
+        self
+    }
+
+    /// Records the events handed to the scenes and the duration of every frame, and writes
+    /// them to `path` when the application stops or panics. Until then, frames are appended
+    /// to a journal next to it, which `replay` reads if the application was killed. Failing
+    /// to write the recording stops the application with the error.
+    pub fn record<R>(mut self, path: R) -> Self
+    where
+        R: AsRef<Path>,
+    {
+        self.record_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Replays the recording at `path` without a window instead of running normally.
+    pub fn replay<R>(mut self, path: R) -> Self
+    where
+        R: AsRef<Path>,
+    {
+        self.replay_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds the serializable component registered as `name` to the checksums of the
+    /// recorded frames. Building fails if no serializable component is registered as `name`.
+    pub fn with_checksum_component(mut self, name: &str) -> Self {
+        self.checksum_components.push(name.to_string());
+        self
+    }
+
+    /// A sender other threads can use to hand events to the scenes as `Event::User`.
+    pub fn event_sender(&self) -> EventSender {
+        self.event_sender.clone()
+    }
+
+    /// Registers the event type `T`, inserting an `Events<T>` resource whose events are
+    /// kept for the frame they are sent in and the following one.
+    pub fn with_event<T>(mut self) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        if self.events.register::<T>() {
+            self.world.resources.insert(Events::<T>::default());
         }
+        self
+    }
+
+    /// Adds `plugin`, which is built along with the application.
+    /// See the [`plugin`](../plugin/index.html) module.
+    pub fn with_plugin<T>(mut self, plugin: T) -> Self
+    where
+        T: Plugin,
+    {
+        let plugin = PendingPlugin::new(plugin);
+        if self.plugin_ids.contains(&plugin.id()) {
+            self.duplicate_plugin.get_or_insert(plugin.name());
+        } else {
+            self.plugin_ids.push(plugin.id());
+            self.plugins.push(plugin);
+        }
+        self
     }
 
     pub fn with_resource<R>(mut self, resource: R) -> Self
@@ -199,60 +1087,253 @@ where
         self
     }
 
-    pub fn with_system<B>(mut self, name: &'static str, mut builder_func: B) -> Self
+    /// Adds the custom `stage`, executed right before `anchor` at the same point of the frame.
+    pub fn with_stage_before(self, stage: Stage, anchor: Stage) -> Self {
+        self.with_stage(stage, anchor, false)
+    }
+
+    /// Adds the custom `stage`, executed right after `anchor` at the same point of the frame.
+    pub fn with_stage_after(self, stage: Stage, anchor: Stage) -> Self {
+        self.with_stage(stage, anchor, true)
+    }
+
+    fn with_stage(mut self, stage: Stage, anchor: Stage, after: bool) -> Self {
+        if self.stage_error.is_none() {
+            self.stage_error = self.stages.insert(stage, anchor, after).err();
+        }
+        self
+    }
+
+    pub fn with_system<B>(self, name: &'static str, builder_func: B) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Schedulable>,
+    {
+        self.with_system_in_stage(Stage::Update, name, builder_func)
+    }
+
+    pub fn with_system_in_stage<B>(
+        mut self,
+        stage: Stage,
+        name: &'static str,
+        mut builder_func: B,
+    ) -> Self
     where
         B: FnMut(&mut World, SystemBuilder) -> Box<dyn Schedulable>,
     {
-        self.schedule_builder = self
-            .schedule_builder
+        self.steps
+            .get_mut(stage)
             .add_system(builder_func(&mut self.world, SystemBuilder::new(name)));
         self
     }
 
-    pub fn with_thread_local_system<B>(mut self, name: &'static str, mut builder_func: B) -> Self
+    pub fn with_thread_local_system<B>(self, name: &'static str, builder_func: B) -> Self
     where
         B: FnMut(&mut World, SystemBuilder) -> Box<dyn Runnable>,
     {
-        self.schedule_builder = self
-            .schedule_builder
+        self.with_thread_local_system_in_stage(Stage::Update, name, builder_func)
+    }
+
+    pub fn with_thread_local_system_in_stage<B>(
+        mut self,
+        stage: Stage,
+        name: &'static str,
+        mut builder_func: B,
+    ) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Runnable>,
+    {
+        self.steps
+            .get_mut(stage)
             .add_thread_local(builder_func(&mut self.world, SystemBuilder::new(name)));
         self
     }
 
-    pub fn with_thread_local_fn<F>(mut self, func: F) -> Self
+    pub fn with_thread_local_fn<F>(self, func: F) -> Self
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.with_thread_local_fn_in_stage(Stage::Update, func)
+    }
+
+    pub fn with_thread_local_fn_in_stage<F>(mut self, stage: Stage, func: F) -> Self
     where
         F: FnMut(&mut World) + 'static,
     {
-        self.schedule_builder = self.schedule_builder.add_thread_local_fn(func);
+        self.steps.get_mut(stage).add_thread_local_fn(func);
         self
     }
 
-    pub fn flush(mut self) -> Self {
-        self.schedule_builder = self.schedule_builder.flush();
+    /// Flushes the command buffers of the systems added to `Stage::Update` so far. Same as
+    /// `flush_stage(Stage::Update)`.
+    pub fn flush(self) -> Self {
+        self.flush_stage(Stage::Update)
+    }
+
+    /// Flushes the command buffers of the systems added to `stage` so far.
+    pub fn flush_stage(mut self, stage: Stage) -> Self {
+        self.steps.get_mut(stage).flush();
         self
     }
 
-    pub fn build(self) -> Application {
-        let settings: Settings = {
-            let mut file = File::open(
-                self.working_directory
-                    .as_ref()
-                    .join(APPLICATION_SETTINGS_FILE_NAME),
-            )
-            .expect("Failed to open settings.yml file.");
-            let mut buffer = vec![];
-            file.read_to_end(&mut buffer)
-                .expect("Failed to read settings.yml file");
+    /// Builds the plugins, then the plugins they added, until none is left. Plugins whose
+    /// dependencies are added by other plugins wait for them.
+    fn build_plugins(mut self) -> Result<Self> {
+        let mut built = Vec::new();
+        let mut deferred = Vec::new();
+        while !(self.plugins.is_empty() && deferred.is_empty()) && self.duplicate_plugin.is_none() {
+            let mut pending = mem::take(&mut deferred);
+            pending.append(&mut self.plugins);
+            let (now, later) = plugin::order(pending, &built)?;
+            for plugin in now {
+                self = plugin.build(self);
+                built.push(plugin.id());
+            }
+            deferred = later;
+        }
+
+        match self.duplicate_plugin {
+            Some(plugin) => Err(Error::Plugin {
+                plugin: plugin.to_string(),
+                message: "was added more than once".to_string(),
+            }),
+            None => Ok(self),
+        }
+    }
+
+    pub fn build(self) -> Result<Application> {
+        self.build_plugins()?.build_application()
+    }
+
+    fn build_application(self) -> Result<Application> {
+        if let Some(error) = self.stage_error {
+            return Err(error);
+        }
+        if let Some(error) = self.component_error {
+            return Err(error);
+        }
+        if let Some(stage) = self
+            .steps
+            .stages()
+            .find(|&stage| !self.stages.contains(stage))
+        {
+            return Err(Error::Stage {
+                stage: stage.to_string(),
+                message: "has systems but was not added".to_string(),
+            });
+        }
+
+        // Layers, from lowest to highest priority: built-in defaults, project settings,
+        // per-user settings, `CRIUS_*` environment variables and command-line overrides.
+        let (settings, settings_loader) = match self.settings {
+            Some(settings) => {
+                settings.validate()?;
+                (settings, None)
+            }
+            None => {
+                let mut loader = SettingsLoader::new().with_file(find_settings_file(
+                    &self.working_directory,
+                    APPLICATION_SETTINGS_FILE_STEM,
+                ));
+
+                if let Some(user_settings) = self.user_settings {
+                    loader = loader.with_optional_file(user_settings);
+                }
+
+                let loader = loader
+                    .with_env_prefix(SETTINGS_ENVIRONMENT_PREFIX)
+                    .with_args(self.settings_args);
+                (loader.load()?, Some(loader))
+            }
+        };
+
+        for name in &self.checksum_components {
+            if self.registry.serializable_named(name).is_none() {
+                return Err(Error::Component {
+                    component: name.clone(),
+                    message: "is not a registered serializable component".to_string(),
+                });
+            }
+        }
+        let checksum_components = self.checksum_components;
+        let recorder = self
+            .record_path
+            .map(|path| Recorder::new(path, checksum_components));
+        let replay = self.replay_path.map(Replay::load).transpose()?;
+        // Replays bring their own gamepad events.
+        let gamepad_backend = match self.gamepad_backend {
+            None if replay.is_none() => default_gamepad_backend(),
+            backend => backend,
+        };
+
+        let mut world = self.world;
+        world.resources.insert(self.registry);
+        world.resources.insert(self.stages.clone());
+        let schedules = self.steps.build(&self.events.resource_ids());
+        world.resources.insert(self.events);
+
+        let assets_path = self.working_directory.join(&settings.assets_path);
+        let vfs = Vfs::new(assets_path.clone());
+        for mount in &settings.mounts {
+            vfs.mount(&mount.path, mount.priority)?;
+        }
+        world.resources.insert(Prefabs::with_vfs(vfs.clone()));
+        world
+            .resources
+            .insert(AssetServer::with_loaders(vfs.clone(), self.asset_loaders));
+        world.resources.insert(vfs);
+        world
+            .resources
+            .insert(EventChannel::<FileChanged>::default());
+        world
+            .resources
+            .insert(EventChannel::<SettingsError>::default());
+        world.resources.insert(InputState::default());
+        world.resources.insert(GamepadState::default());
+        world.resources.insert(WindowCommands::default());
+        world
+            .resources
+            .insert(EventChannel::<WindowError>::default());
+        world
+            .resources
+            .insert(FramePacing::new(settings.frame_pacing));
+        let actions = ActionState::load(
+            find_settings_file(&self.working_directory, BINDINGS_FILE_STEM),
+            self.bindings,
+        )?;
+        let bindings_path = actions.path().to_path_buf();
+        world.resources.insert(actions);
 
-            serde_yaml::from_slice(buffer.as_slice()).expect("Failed to deserialize settings.yml.")
+        // Replays do not pick up changes, so that they run the same every time.
+        let watcher = if settings.watch.enabled && replay.is_none() {
+            let mut watcher =
+                FileWatcher::new(Duration::from_secs_f64(settings.watch.poll_interval));
+            for file in settings_loader.iter().flat_map(SettingsLoader::files) {
+                watcher.watch(file);
+            }
+            watcher.watch(bindings_path);
+            watcher.watch(assets_path.clone());
+            Some(watcher)
+        } else {
+            None
         };
 
-        Application {
+        Ok(Application {
             universe: self.universe,
-            world: self.world,
+            world,
             scene_manager: self.scene_manager,
-            schedule: self.schedule_builder.build(),
+            stages: self.stages,
+            schedules,
             settings,
-        }
+            settings_loader,
+            assets_path,
+            watcher,
+            gamepad_backend,
+            recorder,
+            replay,
+            event_sender: self.event_sender,
+            headless: self.headless,
+            last_frame: Instant::now(),
+            scene_error: None,
+        })
     }
 }
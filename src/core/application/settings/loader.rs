@@ -0,0 +1,505 @@
+use super::Settings;
+use crate::core::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file formats settings and saved worlds can be written in, picked by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+    Ron,
+}
+
+impl Format {
+    /// Every supported format, in the order they are probed for.
+    pub const ALL: [Format; 4] = [Format::Yaml, Format::Toml, Format::Json, Format::Ron];
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Yaml => &["yml", "yaml"],
+            Format::Toml => &["toml"],
+            Format::Json => &["json"],
+            Format::Ron => &["ron"],
+        }
+    }
+
+    pub fn from_path<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    /// Deserializes `contents`, reporting errors against `path`.
+    pub fn parse<T>(self, path: &Path, contents: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let parse_error = |location: Option<(usize, usize)>, message: String| Error::Parse {
+            path: path.to_path_buf(),
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+            message,
+        };
+
+        match self {
+            Format::Yaml => serde_yaml::from_str(contents).map_err(|error| {
+                let location = error
+                    .location()
+                    .map(|location| (location.line(), location.column()));
+                parse_error(location, error.to_string())
+            }),
+            Format::Toml => toml::from_str(contents).map_err(|error| {
+                let location = error
+                    .line_col()
+                    .map(|(line, column)| (line + 1, column + 1));
+                parse_error(location, error.to_string())
+            }),
+            Format::Json => serde_json::from_str(contents).map_err(|error| {
+                let location = Some((error.line(), error.column())).filter(|&(line, _)| line > 0);
+                parse_error(location, error.to_string())
+            }),
+            Format::Ron => ron::de::from_str(contents).map_err(|error| {
+                let location = match &error {
+                    ron::de::Error::Parser(_, position) => Some((position.line, position.col)),
+                    _ => None,
+                };
+                parse_error(location, error.to_string())
+            }),
+        }
+    }
+
+    /// Serializes `value`, reporting errors against `path`.
+    pub fn serialize<T>(self, path: &Path, value: &T) -> Result<String>
+    where
+        T: Serialize,
+    {
+        let contents = match self {
+            Format::Yaml => serde_yaml::to_string(value).map_err(|error| error.to_string()),
+            Format::Toml => toml::to_string_pretty(value).map_err(|error| error.to_string()),
+            Format::Json => serde_json::to_string_pretty(value).map_err(|error| error.to_string()),
+            Format::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+                .map_err(|error| error.to_string()),
+        };
+
+        contents.map_err(|message| Error::Serialize {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+}
+
+/// Finds the settings file named `stem` in `directory`, trying every supported extension.
+/// Returns the YAML path when none exists, so that the error names the canonical file.
+pub(crate) fn find_settings_file(directory: &Path, stem: &str) -> PathBuf {
+    Format::ALL
+        .iter()
+        .flat_map(|format| format.extensions())
+        .map(|extension| directory.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| directory.join(format!("{}.yml", stem)))
+}
+
+#[derive(Clone)]
+enum Layer {
+    File { path: PathBuf, required: bool },
+    Environment { prefix: String },
+    Override { key: String, value: String },
+    Arguments(Vec<String>),
+}
+
+/// Assembles `Settings` from layers, each one overriding the fields set by the previous ones.
+///
+/// The built-in defaults are always the bottom layer. Files, environment variables and
+/// command-line overrides are applied on top of them in the order they were added.
+///
+/// - Files may be YAML, TOML, JSON or RON, picked by extension, and may set any subset of fields.
+/// - Environment variables named `<PREFIX>_<PATH>` set the field at `<PATH>`, where nested
+///   fields are separated by a double underscore, e.g. `CRIUS_WINDOW__TITLE`.
+/// - Command-line arguments of the form `--set window.title=Demo` or `--set=window.title=Demo`
+///   set the field at the dotted path. Other arguments are ignored.
+///
+/// Override values are parsed as YAML scalars, so `true`, `1024` and `null` keep their types.
+#[derive(Clone, Default)]
+pub struct SettingsLoader {
+    layers: Vec<Layer>,
+}
+
+impl SettingsLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file layer. Loading fails if the file does not exist.
+    pub fn with_file<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.layers.push(Layer::File {
+            path: path.as_ref().to_path_buf(),
+            required: true,
+        });
+        self
+    }
+
+    /// Adds a file layer that is skipped if the file does not exist, e.g. per-user settings.
+    pub fn with_optional_file<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.layers.push(Layer::File {
+            path: path.as_ref().to_path_buf(),
+            required: false,
+        });
+        self
+    }
+
+    /// Adds a layer made of the environment variables starting with `<prefix>_`.
+    pub fn with_env_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.layers.push(Layer::Environment {
+            prefix: prefix.into(),
+        });
+        self
+    }
+
+    /// Sets the field at the dotted `key` path to `value`.
+    pub fn with_override<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.layers.push(Layer::Override {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Adds a layer made of the `--set key=value` pairs found in `args`.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.layers
+            .push(Layer::Arguments(args.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// The paths of the file layers, in the order they were added.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.layers.iter().filter_map(|layer| match layer {
+            Layer::File { path, .. } => Some(path.as_path()),
+            _ => None,
+        })
+    }
+
+    /// Merges every layer and validates the result.
+    pub fn load(&self) -> Result<Settings> {
+        let mut tree = serde_yaml::to_value(Settings::default())
+            .expect("Default settings are always serializable.");
+
+        for layer in &self.layers {
+            match layer {
+                Layer::File { path, required } => {
+                    if !*required && !path.exists() {
+                        continue;
+                    }
+                    merge(&mut tree, read_file(path)?);
+                }
+                Layer::Environment { prefix } => {
+                    for (key, value) in environment_overrides(prefix, std::env::vars()) {
+                        apply_override(&mut tree, &key, &value)?;
+                    }
+                }
+                Layer::Override { key, value } => apply_override(&mut tree, key, value)?,
+                Layer::Arguments(args) => {
+                    for (key, value) in argument_overrides(args)? {
+                        apply_override(&mut tree, &key, &value)?;
+                    }
+                }
+            }
+        }
+
+        let settings: Settings = serde_yaml::from_value(tree)
+            .map_err(|error| Error::validation("settings", error.to_string()))?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+fn read_file(path: &Path) -> Result<Value> {
+    let format = Format::from_path(path).ok_or_else(|| Error::Parse {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        message: "unsupported settings file extension".to_string(),
+    })?;
+
+    let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    // Every field has a default, so a file on its own must deserialize into `Settings`.
+    // Doing so reports type errors with the location in the file they come from.
+    format.parse::<Settings>(path, &contents)?;
+    format.parse::<Value>(path, &contents)
+}
+
+/// Merges `layer` into `base`. Mappings are merged key by key, any other value is replaced.
+pub(crate) fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Mapping(base), Value::Mapping(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn apply_override(tree: &mut Value, key: &str, value: &str) -> Result<()> {
+    let path = key.split('.').collect::<Vec<_>>();
+    if path.iter().any(|segment| segment.is_empty()) {
+        return Err(Error::validation(key, "is not a valid settings path"));
+    }
+
+    let literal = Value::String(value.to_string());
+    let parsed = serde_yaml::from_str(value)
+        .ok()
+        .filter(|parsed| !value.is_empty() && *parsed != literal);
+
+    // Check the override on its own so that a bad value is reported against its key.
+    // A value that does not fit once parsed, e.g. a numeric title, is used verbatim.
+    let check = |value: &Value| {
+        let mut isolated = serde_yaml::to_value(Settings::default())
+            .expect("Default settings are always serializable.");
+        set(&mut isolated, &path, value.clone());
+        serde_yaml::from_value::<Settings>(isolated)
+    };
+
+    let value = match parsed {
+        Some(parsed) if check(&parsed).is_ok() => parsed,
+        _ => {
+            check(&literal).map_err(|error| Error::validation(key, error.to_string()))?;
+            literal
+        }
+    };
+
+    set(tree, &path, value);
+    Ok(())
+}
+
+fn set(tree: &mut Value, path: &[&str], value: Value) {
+    if !tree.is_mapping() {
+        *tree = Value::Mapping(Mapping::new());
+    }
+
+    if let Value::Mapping(mapping) = tree {
+        let key = Value::String(path[0].to_string());
+
+        if path.len() == 1 {
+            mapping.insert(key, value);
+        } else {
+            if mapping.get(&key).is_none() {
+                mapping.insert(key.clone(), Value::Null);
+            }
+            set(mapping.get_mut(&key).unwrap(), &path[1..], value);
+        }
+    }
+}
+
+fn environment_overrides<I>(prefix: &str, vars: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let prefix = format!("{}_", prefix.to_uppercase());
+
+    let mut overrides = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let path = name.strip_prefix(&prefix)?;
+            let key = path
+                .split("__")
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+                .join(".");
+            Some((key, value))
+        })
+        .collect::<Vec<_>>();
+
+    // Environment iteration order is unspecified, sort to keep loading deterministic.
+    overrides.sort();
+    overrides
+}
+
+fn argument_overrides(args: &[String]) -> Result<Vec<(String, String)>> {
+    let mut overrides = vec![];
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let assignment = if arg == "--set" {
+            args.next()
+                .ok_or_else(|| Error::validation("--set", "expected a key=value pair"))?
+        } else if let Some(assignment) = arg.strip_prefix("--set=") {
+            assignment
+        } else {
+            continue;
+        };
+
+        match assignment.find('=') {
+            Some(index) => overrides.push((
+                assignment[..index].trim().to_string(),
+                assignment[index + 1..].to_string(),
+            )),
+            None => {
+                return Err(Error::validation(
+                    "--set",
+                    format!("expected a key=value pair, found `{}`", assignment),
+                ))
+            }
+        }
+    }
+
+    Ok(overrides)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_format_from_extension() {
+        assert_eq!(Format::from_path("settings.yml"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("settings.YAML"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("settings.toml"), Some(Format::Toml));
+        assert_eq!(Format::from_path("settings.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("settings.ron"), Some(Format::Ron));
+        assert_eq!(Format::from_path("settings.ini"), None);
+    }
+
+    #[test]
+    fn parses_every_format() {
+        let sources = [
+            ("settings.yml", "window:\n  title: Demo\n"),
+            ("settings.toml", "[window]\ntitle = \"Demo\"\n"),
+            ("settings.json", "{\"window\": {\"title\": \"Demo\"}}"),
+            ("settings.ron", "(window: Some((title: \"Demo\")))"),
+        ];
+
+        for (name, contents) in sources.iter() {
+            let path = Path::new(name);
+            let settings: Settings = Format::from_path(path)
+                .unwrap()
+                .parse(path, contents)
+                .unwrap();
+
+            assert_eq!(settings.window.unwrap().title, "Demo", "{}", name);
+        }
+    }
+
+    #[test]
+    fn merges_nested_mappings() {
+        let mut tree = serde_yaml::to_value(Settings::default()).unwrap();
+        merge(
+            &mut tree,
+            serde_yaml::from_str("window:\n  title: Base\n  decorations: false\n").unwrap(),
+        );
+        merge(
+            &mut tree,
+            serde_yaml::from_str("window:\n  title: User\n").unwrap(),
+        );
+
+        let window = serde_yaml::from_value::<Settings>(tree)
+            .unwrap()
+            .window
+            .unwrap();
+        assert_eq!(window.title, "User");
+        assert!(!window.decorations);
+    }
+
+    #[test]
+    fn maps_environment_variables_to_paths() {
+        let vars = vec![
+            ("CRIUS_WINDOW__TITLE".to_string(), "Env".to_string()),
+            ("CRIUS_ASSETS_PATH".to_string(), "./data".to_string()),
+            ("OTHER_WINDOW__TITLE".to_string(), "Ignored".to_string()),
+        ];
+
+        assert_eq!(
+            environment_overrides("CRIUS", vars),
+            vec![
+                ("assets_path".to_string(), "./data".to_string()),
+                ("window.title".to_string(), "Env".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_set_arguments() {
+        let args = strings(&[
+            "game",
+            "--set",
+            "window.title=My Game",
+            "--verbose",
+            "--set=window.size.width=640",
+        ]);
+
+        assert_eq!(
+            argument_overrides(&args).unwrap(),
+            vec![
+                ("window.title".to_string(), "My Game".to_string()),
+                ("window.size.width".to_string(), "640".to_string()),
+            ]
+        );
+        assert!(argument_overrides(&strings(&["--set", "window.title"])).is_err());
+        assert!(argument_overrides(&strings(&["--set"])).is_err());
+    }
+
+    #[test]
+    fn overrides_are_typed_and_checked() {
+        let settings = SettingsLoader::new()
+            .with_override("window.decorations", "false")
+            .with_override("window.title", "1024")
+            .with_override("time.max_fixed_steps", "8")
+            .load()
+            .unwrap();
+
+        let window = settings.window.unwrap();
+        assert!(!window.decorations);
+        assert_eq!(window.title, "1024");
+        assert_eq!(settings.time.max_fixed_steps, 8);
+
+        match SettingsLoader::new()
+            .with_override("time.max_fixed_steps", "many")
+            .load()
+        {
+            Err(Error::Validation { field, .. }) => assert_eq!(field, "time.max_fixed_steps"),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
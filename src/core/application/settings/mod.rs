@@ -0,0 +1,533 @@
+//! Application settings.
+//!
+//! Settings are assembled from layers, each one overriding the fields set by the
+//! previous ones. See [`SettingsLoader`](struct.SettingsLoader.html) for details.
+
+mod loader;
+
+pub(crate) use self::loader::{find_settings_file, merge};
+pub use self::loader::{Format, SettingsLoader};
+
+use crate::core::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub version: Version,
+    pub assets_path: String,
+    /// Archives and directories mounted on top of the assets directory.
+    pub mounts: Vec<MountSettings>,
+    /// The primary window. The application runs without a window or an event loop when
+    /// neither it nor `windows` is set.
+    pub window: Option<WindowSettings>,
+    /// Windows opened along with the primary one.
+    pub windows: Vec<WindowSettings>,
+    pub time: TimeSettings,
+    pub frame_pacing: FramePacingSettings,
+    pub watch: WatchSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: Version::default(),
+            assets_path: "./assets".to_string(),
+            mounts: Vec::new(),
+            window: None,
+            windows: Vec::new(),
+            time: TimeSettings::default(),
+            frame_pacing: FramePacingSettings::default(),
+            watch: WatchSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads and validates the settings stored in the file at `path`, on top of the defaults.
+    /// The format of the file is picked from its extension.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        SettingsLoader::new().with_file(path).load()
+    }
+
+    /// The primary window followed by the other windows. The first window is the primary
+    /// one when only `windows` is set.
+    pub fn all_windows(&self) -> impl Iterator<Item = &WindowSettings> {
+        self.window.iter().chain(&self.windows)
+    }
+
+    /// Checks that the settings describe something the application can run.
+    pub fn validate(&self) -> Result<()> {
+        let mut names = Vec::new();
+        for window in self.all_windows() {
+            window.validate()?;
+            if names.contains(&window.name.as_str()) {
+                return Err(Error::validation(
+                    "windows",
+                    format!("more than one window is named `{}`", window.name),
+                ));
+            }
+            names.push(&window.name);
+        }
+
+        self.time.validate()?;
+        self.frame_pacing.validate()?;
+        self.watch.validate()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// Identifies the window in the `Windows` resource. Names are unique.
+    pub name: String,
+    pub title: String,
+    pub size: Option<LogicalSize<u32>>,
+    pub min_size: Option<LogicalSize<u32>>,
+    pub max_size: Option<LogicalSize<u32>>,
+    pub resizeable: bool,
+    pub maximized: bool,
+    pub visible: bool,
+    pub transparent: bool,
+    pub decorations: bool,
+    pub always_on_top: bool,
+    pub fullscreen: FullscreenSettings,
+    /// Position of the top-left corner of the window on the desktop. `None` lets the
+    /// platform place the window.
+    pub position: Option<LogicalPosition<i32>>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            name: "main".to_string(),
+            title: "crius".to_string(),
+            size: Some(LogicalSize::new(1024, 768)),
+            min_size: None,
+            max_size: None,
+            resizeable: true,
+            maximized: false,
+            visible: true,
+            transparent: false,
+            decorations: true,
+            always_on_top: false,
+            fullscreen: FullscreenSettings::default(),
+            position: None,
+        }
+    }
+}
+
+impl WindowSettings {
+    /// Checks that `min_size` <= `size` <= `max_size` and that none of them is empty.
+    pub fn validate(&self) -> Result<()> {
+        let sizes = [
+            ("window.min_size", self.min_size),
+            ("window.size", self.size),
+            ("window.max_size", self.max_size),
+        ];
+
+        for (field, size) in sizes.iter() {
+            if let Some(size) = size {
+                if size.width == 0 || size.height == 0 {
+                    return Err(Error::validation(
+                        *field,
+                        format!("{}x{} is empty", size.width, size.height),
+                    ));
+                }
+            }
+        }
+
+        for (i, (smaller_field, smaller)) in sizes.iter().enumerate() {
+            for (larger_field, larger) in sizes.iter().skip(i + 1) {
+                if let (Some(smaller), Some(larger)) = (smaller, larger) {
+                    if smaller.width > larger.width || smaller.height > larger.height {
+                        return Err(Error::validation(
+                            *smaller_field,
+                            format!(
+                                "{}x{} is larger than `{}` ({}x{})",
+                                smaller.width,
+                                smaller.height,
+                                larger_field,
+                                larger.width,
+                                larger.height
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        self.fullscreen.validate()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenMode {
+    Windowed,
+    /// A window covering the whole monitor, without changing its video mode.
+    Borderless,
+    /// Takes over the monitor and switches it to the preferred video mode.
+    Exclusive,
+}
+
+/// A monitor, by its position in the platform's list of monitors or by its name.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MonitorSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonitorSelector::Index(index) => write!(f, "#{}", index),
+            MonitorSelector::Name(name) => write!(f, "`{}`", name),
+        }
+    }
+}
+
+/// The video mode preferred for exclusive fullscreen. The closest mode the monitor
+/// supports is used.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoModeSettings {
+    pub size: PhysicalSize<u32>,
+    /// In hertz. `None` picks the highest rate available at `size`.
+    #[serde(default)]
+    pub refresh_rate: Option<u16>,
+    /// In bits per pixel. `None` picks the deepest mode available.
+    #[serde(default)]
+    pub bit_depth: Option<u16>,
+}
+
+/// How a window covers a monitor.
+///
+/// The primary monitor is used when `monitor` names none of the connected ones, and
+/// borderless fullscreen when the monitor reports no video mode.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FullscreenSettings {
+    pub mode: FullscreenMode,
+    /// `None` uses the monitor the window is on.
+    pub monitor: Option<MonitorSelector>,
+    /// Only used in exclusive mode. `None` uses the largest mode of the monitor.
+    pub video_mode: Option<VideoModeSettings>,
+}
+
+impl Default for FullscreenSettings {
+    fn default() -> Self {
+        Self {
+            mode: FullscreenMode::Windowed,
+            monitor: None,
+            video_mode: None,
+        }
+    }
+}
+
+impl FullscreenSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(video_mode) = &self.video_mode {
+            let size = video_mode.size;
+            if size.width == 0 || size.height == 0 {
+                return Err(Error::validation(
+                    "window.fullscreen.video_mode.size",
+                    format!("{}x{} is empty", size.width, size.height),
+                ));
+            }
+
+            if video_mode.refresh_rate == Some(0) {
+                return Err(Error::validation(
+                    "window.fullscreen.video_mode.refresh_rate",
+                    "0 is not a refresh rate",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct TimeSettings {
+    /// Duration of a single `Scene::fixed_update` step, in seconds.
+    pub fixed_timestep: f64,
+    /// Maximum number of fixed steps run in a single frame.
+    /// Frame time beyond that is dropped to avoid a spiral of death.
+    pub max_fixed_steps: u32,
+}
+
+impl Default for TimeSettings {
+    fn default() -> Self {
+        Self {
+            fixed_timestep: 1.0 / 60.0,
+            max_fixed_steps: 5,
+        }
+    }
+}
+
+impl TimeSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !self.fixed_timestep.is_finite() || self.fixed_timestep <= 0.0 {
+            return Err(Error::validation(
+                "time.fixed_timestep",
+                format!(
+                    "{} is not a positive number of seconds",
+                    self.fixed_timestep
+                ),
+            ));
+        }
+
+        if self.max_fixed_steps == 0 {
+            return Err(Error::validation(
+                "time.max_fixed_steps",
+                "at least one fixed step per frame is required",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// When the main loop runs frames.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PacingMode {
+    /// Runs frames back to back, at most `max_fps` times per second if it is set.
+    Poll,
+    /// Runs a frame only after events arrive, for tools and menus that have nothing to do
+    /// otherwise.
+    Wait,
+    /// Runs a frame after events arrive, or `max_fps` times per second when none do.
+    WaitUntil,
+}
+
+/// Settings for how often the windowed main loop runs frames. Headless applications are
+/// paced by [`HeadlessSettings`](struct.HeadlessSettings.html) instead.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FramePacingSettings {
+    pub mode: PacingMode,
+    /// Frames per second the `poll` mode is capped to and the `wait_until` mode wakes at.
+    pub max_fps: Option<f64>,
+    /// Frames per second the application is capped to while its window is unfocused or
+    /// minimized. `None` keeps the usual rate.
+    pub background_fps: Option<f64>,
+}
+
+impl Default for FramePacingSettings {
+    fn default() -> Self {
+        Self {
+            mode: PacingMode::Poll,
+            max_fps: None,
+            background_fps: None,
+        }
+    }
+}
+
+impl FramePacingSettings {
+    pub fn validate(&self) -> Result<()> {
+        let rates = [
+            ("frame_pacing.max_fps", self.max_fps),
+            ("frame_pacing.background_fps", self.background_fps),
+        ];
+        for (field, rate) in rates.iter() {
+            if let Some(rate) = rate {
+                if !rate.is_finite() || *rate <= 0.0 {
+                    return Err(Error::validation(
+                        *field,
+                        format!("{} is not a positive number of frames per second", rate),
+                    ));
+                }
+            }
+        }
+
+        if self.mode == PacingMode::WaitUntil && self.max_fps.is_none() {
+            return Err(Error::validation(
+                "frame_pacing.max_fps",
+                "the `wait_until` mode requires a frame rate",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// An archive or directory to mount in the application's `Vfs`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MountSettings {
+    /// Path of the archive or directory, relative to the assets directory.
+    pub path: String,
+    /// Files are read from the mount with the highest priority that has them.
+    /// The assets directory itself is mounted at priority 0.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Settings for reloading the settings files and watching the assets directory while the
+/// application runs.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct WatchSettings {
+    /// Defaults to `true` in debug builds only, since every scan reads the metadata of every
+    /// file under the assets directory.
+    pub enabled: bool,
+    /// Time between two scans of the watched files, in seconds.
+    pub poll_interval: f64,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            poll_interval: 0.5,
+        }
+    }
+}
+
+impl WatchSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !self.poll_interval.is_finite() || self.poll_interval < 0.0 {
+            return Err(Error::validation(
+                "watch.poll_interval",
+                format!(
+                    "{} is not a non-negative number of seconds",
+                    self.poll_interval
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings for running an application without a window or an event loop.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct HeadlessSettings {
+    /// Number of frames per second the main loop is throttled to.
+    /// `None` runs frames back to back as fast as possible.
+    pub tick_rate: Option<u32>,
+    /// Number of frames to run before the application exits on its own.
+    /// `None` runs until the scene stack is empty or a scene quits.
+    pub max_frames: Option<u64>,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        Self {
+            tick_rate: Some(60),
+            max_frames: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_settings() -> WindowSettings {
+        WindowSettings {
+            size: Some(LogicalSize::new(800, 600)),
+            ..WindowSettings::default()
+        }
+    }
+
+    #[test]
+    fn accepts_ordered_window_sizes() {
+        let window = WindowSettings {
+            min_size: Some(LogicalSize::new(640, 480)),
+            max_size: Some(LogicalSize::new(1920, 1080)),
+            ..window_settings()
+        };
+
+        assert!(window.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_size_below_min_size() {
+        let window = WindowSettings {
+            min_size: Some(LogicalSize::new(1024, 480)),
+            ..window_settings()
+        };
+
+        match window.validate() {
+            Err(Error::Validation { field, .. }) => assert_eq!(field, "window.min_size"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_size_above_max_size() {
+        let window = WindowSettings {
+            max_size: Some(LogicalSize::new(800, 400)),
+            ..window_settings()
+        };
+
+        match window.validate() {
+            Err(Error::Validation { field, .. }) => assert_eq!(field, "window.size"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_windows_with_the_same_name() {
+        let mut settings = Settings {
+            window: Some(window_settings()),
+            windows: vec![window_settings()],
+            ..Settings::default()
+        };
+
+        match settings.validate() {
+            Err(Error::Validation { field, .. }) => assert_eq!(field, "windows"),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        settings.windows[0].name = "inspector".to_string();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_non_positive_fixed_timestep() {
+        let time = TimeSettings {
+            fixed_timestep: 0.0,
+            ..TimeSettings::default()
+        };
+
+        assert!(time.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_wait_until_without_a_frame_rate() {
+        let pacing = FramePacingSettings {
+            mode: PacingMode::WaitUntil,
+            ..FramePacingSettings::default()
+        };
+
+        match pacing.validate() {
+            Err(Error::Validation { field, .. }) => assert_eq!(field, "frame_pacing.max_fps"),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let pacing = FramePacingSettings {
+            max_fps: Some(30.0),
+            ..pacing
+        };
+        assert!(pacing.validate().is_ok());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::core::error::Result;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Turns the contents of asset files into assets.
+///
+/// Loaders run on the asset server's background threads.
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+
+    /// The file extensions handled by the loader, without the leading dot.
+    fn extensions(&self) -> &[&str];
+
+    /// Creates the asset from the contents of the file at `path`.
+    fn load(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Asset>;
+}
+
+pub(crate) type BoxedAsset = Arc<dyn Any + Send + Sync>;
+
+pub(crate) trait ErasedAssetLoader: Send + Sync {
+    fn asset_type(&self) -> TypeId;
+
+    fn load(&self, path: &Path, bytes: Vec<u8>) -> Result<BoxedAsset>;
+}
+
+impl<L> ErasedAssetLoader for L
+where
+    L: AssetLoader,
+{
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<L::Asset>()
+    }
+
+    fn load(&self, path: &Path, bytes: Vec<u8>) -> Result<BoxedAsset> {
+        AssetLoader::load(self, path, bytes).map(|asset| Arc::new(asset) as BoxedAsset)
+    }
+}
+
+/// The loaders of an asset server, by lowercase file extension.
+#[derive(Clone, Default)]
+pub(crate) struct AssetLoaders {
+    loaders: HashMap<String, Arc<dyn ErasedAssetLoader>>,
+}
+
+impl AssetLoaders {
+    /// Adds `loader`, replacing the loaders previously added for the same extensions.
+    pub(crate) fn add<L>(&mut self, loader: L)
+    where
+        L: AssetLoader,
+    {
+        let extensions = loader
+            .extensions()
+            .iter()
+            .map(|extension| extension.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let loader: Arc<dyn ErasedAssetLoader> = Arc::new(loader);
+        for extension in extensions {
+            self.loaders.insert(extension, loader.clone());
+        }
+    }
+
+    pub(crate) fn find(&self, path: &Path) -> Option<Arc<dyn ErasedAssetLoader>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.loaders.get(&extension).cloned()
+    }
+}
@@ -0,0 +1,422 @@
+//! Loading assets from the assets directory in the background.
+//!
+//! The application inserts an [`AssetServer`] reading from its `Vfs` into
+//! `world.resources`, with the loaders registered through
+//! `ApplicationBuilder::with_asset_loader`. Loading returns a [`Handle`] right away, and
+//! the asset becomes available once a background thread has loaded it. An asset is
+//! unloaded when the last handle to it is dropped.
+//!
+//! [`AssetServer`]: struct.AssetServer.html
+//! [`Handle`]: struct.Handle.html
+
+mod loader;
+mod pool;
+
+pub use self::loader::AssetLoader;
+pub(crate) use self::loader::AssetLoaders;
+
+use self::loader::BoxedAsset;
+use self::pool::ThreadPool;
+use crate::core::vfs::Vfs;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread;
+
+const MAX_LOADER_THREADS: usize = 4;
+
+/// Where the asset behind a handle is in its loading.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Pending,
+    Loaded,
+    /// Loading failed, with the error message.
+    Failed(String),
+}
+
+type AssetKey = (PathBuf, TypeId);
+
+enum AssetState {
+    Pending,
+    Loaded(BoxedAsset),
+    Failed(String),
+}
+
+struct AssetEntry {
+    id: u64,
+    handle: Weak<HandleInner>,
+    state: AssetState,
+}
+
+type Storage = Mutex<HashMap<AssetKey, AssetEntry>>;
+
+/// Locks `storage`, which stays usable after a thread panicked while holding it.
+fn lock(storage: &Storage) -> MutexGuard<'_, HashMap<AssetKey, AssetEntry>> {
+    storage.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct HandleInner {
+    id: u64,
+    key: AssetKey,
+    storage: Weak<Storage>,
+}
+
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        if let Some(storage) = self.storage.upgrade() {
+            let mut entries = lock(&storage);
+            // The entry may already have been replaced by a new load of the same path.
+            let removed = if entries
+                .get(&self.key)
+                .is_some_and(|entry| entry.id == self.id)
+            {
+                entries.remove(&self.key)
+            } else {
+                None
+            };
+            // The asset may hold handles of its own, which lock the storage when dropped.
+            drop(entries);
+            drop(removed);
+        }
+    }
+}
+
+/// A reference counted handle to an asset of type `T`. Cloning a handle is cheap.
+pub struct Handle<T> {
+    inner: Arc<HandleInner>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// The path of the asset, relative to the assets directory.
+    pub fn path(&self) -> &Path {
+        &self.inner.key.0
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.id == other.inner.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.inner.id)
+            .field("path", &self.inner.key.0)
+            .finish()
+    }
+}
+
+/// Loads assets relative to the assets directory with the loader registered for their
+/// file extension.
+pub struct AssetServer {
+    vfs: Vfs,
+    loaders: AssetLoaders,
+    storage: Arc<Storage>,
+    next_id: AtomicU64,
+    pool: ThreadPool,
+}
+
+impl AssetServer {
+    /// Creates a server loading the files under `root`.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self::with_loaders(Vfs::new(root), AssetLoaders::default())
+    }
+
+    /// Creates a server loading the files of `vfs`.
+    pub fn with_vfs(vfs: Vfs) -> Self {
+        Self::with_loaders(vfs, AssetLoaders::default())
+    }
+
+    pub(crate) fn with_loaders(vfs: Vfs, loaders: AssetLoaders) -> Self {
+        let threads = thread::available_parallelism()
+            .map_or(1, |threads| threads.get())
+            .min(MAX_LOADER_THREADS);
+
+        Self {
+            vfs,
+            loaders,
+            storage: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(0),
+            pool: ThreadPool::new("crius-assets", threads),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.vfs.root()
+    }
+
+    /// Adds `loader`, replacing the loaders previously added for the same extensions.
+    pub fn add_loader<L>(&mut self, loader: L)
+    where
+        L: AssetLoader,
+    {
+        self.loaders.add(loader);
+    }
+
+    /// Starts loading the asset at `path`, relative to the assets directory.
+    ///
+    /// Loading an asset that is still referenced by a handle returns a clone of that handle.
+    /// Loading fails when no loader is registered for the extension of `path`, or when that
+    /// loader does not produce `T`s.
+    pub fn load<T, P>(&self, path: P) -> Handle<T>
+    where
+        T: Send + Sync + 'static,
+        P: AsRef<Path>,
+    {
+        let key = (path.as_ref().to_path_buf(), TypeId::of::<T>());
+        let mut entries = lock(&self.storage);
+
+        if let Some(inner) = entries.get(&key).and_then(|entry| entry.handle.upgrade()) {
+            return Handle {
+                inner,
+                marker: PhantomData,
+            };
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let inner = Arc::new(HandleInner {
+            id,
+            key: key.clone(),
+            storage: Arc::downgrade(&self.storage),
+        });
+
+        let state = match self.loaders.find(&key.0) {
+            None => AssetState::Failed(format!("no asset loader for {}", key.0.display())),
+            Some(loader) if loader.asset_type() != key.1 => AssetState::Failed(format!(
+                "the asset loader for {} does not load the requested type",
+                key.0.display()
+            )),
+            Some(loader) => {
+                let vfs = self.vfs.clone();
+                let storage = Arc::downgrade(&self.storage);
+                let key = key.clone();
+
+                self.pool.execute(move || {
+                    let state = panic::catch_unwind(AssertUnwindSafe(|| {
+                        vfs.read(&key.0)
+                            .and_then(|bytes| loader.load(&vfs.root().join(&key.0), bytes))
+                    }));
+                    let state = match state {
+                        Ok(Ok(asset)) => AssetState::Loaded(asset),
+                        Ok(Err(error)) => AssetState::Failed(error.to_string()),
+                        Err(_) => AssetState::Failed("loader panicked".to_string()),
+                    };
+
+                    if let Some(storage) = storage.upgrade() {
+                        let mut entries = lock(&storage);
+                        match entries.get_mut(&key) {
+                            Some(entry) if entry.id == id => entry.state = state,
+                            // Every handle was dropped while the asset was loading.
+                            _ => {}
+                        }
+                    }
+                });
+
+                AssetState::Pending
+            }
+        };
+
+        let replaced = entries.insert(
+            key,
+            AssetEntry {
+                id,
+                handle: Arc::downgrade(&inner),
+                state,
+            },
+        );
+        drop(entries);
+        drop(replaced);
+
+        Handle {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn load_state<T>(&self, handle: &Handle<T>) -> LoadState {
+        let entries = lock(&self.storage);
+        match entries.get(&handle.inner.key).map(|entry| &entry.state) {
+            Some(AssetState::Pending) | None => LoadState::Pending,
+            Some(AssetState::Loaded(_)) => LoadState::Loaded,
+            Some(AssetState::Failed(message)) => LoadState::Failed(message.clone()),
+        }
+    }
+
+    /// The asset behind `handle`, once it is loaded.
+    pub fn get<T>(&self, handle: &Handle<T>) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        let entries = lock(&self.storage);
+        match entries.get(&handle.inner.key).map(|entry| &entry.state) {
+            Some(AssetState::Loaded(asset)) => asset.clone().downcast::<T>().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether every asset being loaded has finished loading, successfully or not.
+    pub fn is_idle(&self) -> bool {
+        lock(&self.storage)
+            .values()
+            .all(|entry| !matches!(entry.state, AssetState::Pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::error::Result;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    struct TextLoader;
+
+    impl AssetLoader for TextLoader {
+        type Asset = String;
+
+        fn extensions(&self) -> &[&str] {
+            &["txt"]
+        }
+
+        fn load(&self, _path: &Path, bytes: Vec<u8>) -> Result<String> {
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+
+    struct PanickingLoader;
+
+    impl AssetLoader for PanickingLoader {
+        type Asset = String;
+
+        fn extensions(&self) -> &[&str] {
+            &["bad"]
+        }
+
+        fn load(&self, _path: &Path, _bytes: Vec<u8>) -> Result<String> {
+            panic!("broken asset")
+        }
+    }
+
+    fn server(name: &str) -> AssetServer {
+        let root =
+            std::env::temp_dir().join(format!("crius-assets-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("greeting.txt"), "hello").unwrap();
+
+        let mut server = AssetServer::new(root);
+        server.add_loader(TextLoader);
+        server
+    }
+
+    fn wait_until_idle(server: &AssetServer) {
+        let start = Instant::now();
+        while !server.is_idle() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "assets took too long to load"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn loads_assets_in_the_background() {
+        let server = server("loads");
+        let handle = server.load::<String, _>("greeting.txt");
+        let missing = server.load::<String, _>("missing.txt");
+        let unknown = server.load::<String, _>("greeting.png");
+        wait_until_idle(&server);
+        fs::remove_dir_all(server.root()).unwrap();
+
+        assert_eq!(server.load_state(&handle), LoadState::Loaded);
+        assert_eq!(*server.get(&handle).unwrap(), "hello");
+        assert!(matches!(server.load_state(&missing), LoadState::Failed(_)));
+        assert!(matches!(server.load_state(&unknown), LoadState::Failed(_)));
+        assert!(server.get(&missing).is_none());
+    }
+
+    #[test]
+    fn fails_assets_whose_loader_panics() {
+        let mut server = server("panics");
+        fs::write(server.root().join("broken.bad"), "").unwrap();
+        server.add_loader(PanickingLoader);
+        server.pool = ThreadPool::new("crius-assets-test", 1);
+
+        let broken = server.load::<String, _>("broken.bad");
+        let handle = server.load::<String, _>("greeting.txt");
+        wait_until_idle(&server);
+        fs::remove_dir_all(server.root()).unwrap();
+
+        assert_eq!(
+            server.load_state(&broken),
+            LoadState::Failed("loader panicked".to_string())
+        );
+        assert_eq!(*server.get(&handle).unwrap(), "hello");
+    }
+
+    #[test]
+    fn unloads_assets_with_their_last_handle() {
+        let server = server("unloads");
+        let handle = server.load::<String, _>("greeting.txt");
+        let clone = server.load::<String, _>("greeting.txt");
+        assert_eq!(handle, clone);
+        wait_until_idle(&server);
+
+        drop(handle);
+        assert_eq!(*server.get(&clone).unwrap(), "hello");
+
+        drop(clone);
+        assert!(server.storage.lock().unwrap().is_empty());
+        fs::remove_dir_all(server.root()).unwrap();
+    }
+
+    #[test]
+    fn unloads_assets_holding_handles_to_other_assets() {
+        let server = server("nested");
+        let greeting = server.load::<String, _>("greeting.txt");
+        let holder = server.load::<Handle<String>, _>("holder.ref");
+        wait_until_idle(&server);
+
+        let asset: BoxedAsset = Arc::new(greeting);
+        server
+            .storage
+            .lock()
+            .unwrap()
+            .get_mut(&holder.inner.key)
+            .unwrap()
+            .state = AssetState::Loaded(asset);
+
+        drop(holder);
+        assert!(server.storage.lock().unwrap().is_empty());
+        fs::remove_dir_all(server.root()).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of threads running jobs in the order they are submitted. A job that panics
+/// does not take its thread down with it.
+pub(crate) struct ThreadPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub(crate) fn new(name: &str, threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1))
+            .map(|index| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("{}-{}", name, index))
+                    .spawn(move || loop {
+                        // The lock is released before running the job.
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        match job {
+                            Ok(job) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                            }
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn a thread pool worker.")
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub(crate) fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(sender) = &self.sender {
+            // Workers only stop once the sender is dropped, so sending cannot fail.
+            let _ = sender.send(Box::new(job));
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    /// Waits for the submitted jobs to finish.
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
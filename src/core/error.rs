@@ -0,0 +1,109 @@
+//! Errors reported by crius.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be opened, read or written.
+    Io { path: PathBuf, source: io::Error },
+    /// A file could not be deserialized.
+    /// `line` and `column` are 1-based and present when the parser reports a location.
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// A value could not be serialized into a file.
+    Serialize { path: PathBuf, message: String },
+    /// A setting has a value the application cannot use.
+    Validation { field: String, message: String },
+    /// The window described by the settings could not be created.
+    Window(winit::error::OsError),
+    /// A window was opened with the name of a window that is already open.
+    DuplicateWindow { name: String },
+    /// A plugin was added twice, or its dependencies cannot be met.
+    Plugin { plugin: String, message: String },
+    /// A component name was registered for two different types, or does not name a
+    /// registered component.
+    Component { component: String, message: String },
+    /// A stage was added twice, placed relative to a missing stage, or given systems without
+    /// being added.
+    Stage { stage: String, message: String },
+    /// A replayed frame ended with a different checksum than when it was recorded.
+    Diverged {
+        frame: usize,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl Error {
+    pub(crate) fn validation<F, M>(field: F, message: M) -> Self
+    where
+        F: Into<String>,
+        M: Into<String>,
+    {
+        Error::Validation {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "failed to access {}: {}", path.display(), source)
+            }
+            Error::Parse { path, message, .. } => {
+                write!(f, "failed to parse {}: {}", path.display(), message)
+            }
+            Error::Serialize { path, message } => {
+                write!(f, "failed to serialize {}: {}", path.display(), message)
+            }
+            Error::Validation { field, message } => {
+                write!(f, "invalid setting `{}`: {}", field, message)
+            }
+            Error::Window(error) => write!(f, "failed to create window: {}", error),
+            Error::DuplicateWindow { name } => {
+                write!(f, "a window named `{}` is already open", name)
+            }
+            Error::Plugin { plugin, message } => write!(f, "plugin `{}` {}", plugin, message),
+            Error::Component { component, message } => {
+                write!(f, "component `{}` {}", component, message)
+            }
+            Error::Stage { stage, message } => write!(f, "stage `{}` {}", stage, message),
+            Error::Diverged {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "replay diverged at frame {}: checksum {:08x}, expected {:08x}",
+                frame, actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Window(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<winit::error::OsError> for Error {
+    fn from(error: winit::error::OsError) -> Self {
+        Error::Window(error)
+    }
+}
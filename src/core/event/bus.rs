@@ -0,0 +1,222 @@
+//! Typed events passed between systems and scenes.
+//!
+//! Event types are registered with `ApplicationBuilder::with_event`, which inserts an
+//! [`Events<T>`](struct.Events.html) resource. Systems send events by writing to the resource
+//! and read them by reading it through an [`EventReader<T>`](struct.EventReader.html) they
+//! keep between runs, usually obtained from
+//! [`ReadEvents::read_events`](trait.ReadEvents.html#tymethod.read_events) when the system is
+//! built.
+//!
+//! Events are kept for the frame they are sent in and the following one, so every reader
+//! that runs once per frame sees each event exactly once. Within a schedule, systems that
+//! write a registered event type run before the systems that only read it.
+
+use legion::query::Read;
+use legion::resource::{ResourceTypeId, Resources};
+use legion::system::SystemBuilder;
+use std::marker::PhantomData;
+
+/// The events of type `T` sent this frame and the previous one.
+#[derive(Debug)]
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    /// The number of events sent before the first event of `previous`.
+    previous_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// A reader of the events sent from now on.
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader {
+            next: self.sent(),
+            marker: PhantomData,
+        }
+    }
+
+    /// The events still kept, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous.is_empty() && self.current.is_empty()
+    }
+
+    /// Drops the events of the previous frame and starts a new one.
+    pub(crate) fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    /// The number of events sent since the resource was created.
+    fn sent(&self) -> usize {
+        self.previous_start + self.previous.len() + self.current.len()
+    }
+}
+
+/// Reads the events of type `T` not yet seen by this reader.
+///
+/// Readers created with `Default` start with the oldest events still kept.
+#[derive(Debug)]
+pub struct EventReader<T> {
+    next: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    /// The events sent since the previous read, oldest first. Events dropped before they
+    /// were read are skipped.
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let skip = self.next.saturating_sub(events.previous_start);
+        self.next = events.sent();
+        events.iter().skip(skip)
+    }
+}
+
+/// A `SystemBuilder` whose system reads the `Events<T>` resource.
+pub type EventSystemBuilder<T> = SystemBuilder<(), (Read<Events<T>>, ())>;
+
+/// Reading registered events from systems, implemented for the `SystemBuilder` that
+/// `ApplicationBuilder::with_system` and the methods next to it hand out.
+pub trait ReadEvents {
+    /// Gives the system read access to the `Events<T>` resource and returns a reader for the
+    /// system to keep. The events come first among the resources of the system, so this is
+    /// called before adding queries and other resources. The type must be registered with
+    /// `ApplicationBuilder::with_event`.
+    fn read_events<T>(self) -> (EventSystemBuilder<T>, EventReader<T>)
+    where
+        T: Send + Sync + 'static;
+}
+
+impl ReadEvents for SystemBuilder {
+    fn read_events<T>(self) -> (EventSystemBuilder<T>, EventReader<T>)
+    where
+        T: Send + Sync + 'static,
+    {
+        // Events sent before the system was built are kept for it too.
+        (self.read_resource::<Events<T>>(), EventReader::default())
+    }
+}
+
+/// The registered event types, updated by the application at the start of every frame.
+#[derive(Default)]
+pub(crate) struct EventRegistry {
+    types: Vec<EventType>,
+}
+
+struct EventType {
+    /// The id of the `Events` resource of the type.
+    resource: ResourceTypeId,
+    update: fn(&Resources),
+}
+
+impl EventRegistry {
+    pub(crate) fn register<T>(&mut self) -> bool
+    where
+        T: Send + Sync + 'static,
+    {
+        let resource = ResourceTypeId::of::<Events<T>>();
+        if self
+            .types
+            .iter()
+            .any(|registered| registered.resource == resource)
+        {
+            return false;
+        }
+        self.types.push(EventType {
+            resource,
+            update: update::<T>,
+        });
+        true
+    }
+
+    /// The resource ids of the `Events` of every registered type.
+    pub(crate) fn resource_ids(&self) -> Vec<ResourceTypeId> {
+        self.types
+            .iter()
+            .map(|registered| registered.resource)
+            .collect()
+    }
+
+    pub(crate) fn update(&self, resources: &Resources) {
+        for registered in &self.types {
+            (registered.update)(resources);
+        }
+    }
+}
+
+fn update<T>(resources: &Resources)
+where
+    T: Send + Sync + 'static,
+{
+    if let Some(mut events) = resources.get_mut::<Events<T>>() {
+        events.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_events_for_two_frames() {
+        let mut events = Events::default();
+        let mut early = EventReader::default();
+        events.send(1);
+        let mut late = events.reader();
+        events.send(2);
+
+        assert_eq!(early.read(&events).collect::<Vec<_>>(), vec![&1, &2]);
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), vec![&2]);
+
+        events.update();
+        events.send(3);
+        assert_eq!(early.read(&events).collect::<Vec<_>>(), vec![&3]);
+        assert_eq!(early.read(&events).count(), 0);
+
+        events.update();
+        assert_eq!(late.read(&events).collect::<Vec<_>>(), vec![&3]);
+
+        events.update();
+        assert!(events.is_empty());
+        assert_eq!(EventReader::default().read(&events).count(), 0);
+    }
+
+    #[test]
+    fn updates_registered_types() {
+        let mut resources = Resources::default();
+        resources.insert(Events::<u32>::default());
+        let mut registry = EventRegistry::default();
+        assert!(registry.register::<u32>());
+        assert!(!registry.register::<u32>());
+
+        resources.get_mut::<Events<u32>>().unwrap().send(7);
+        registry.update(&resources);
+        registry.update(&resources);
+        assert!(resources.get::<Events<u32>>().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+mod bus;
+
+pub(crate) use self::bus::EventRegistry;
+pub use self::bus::{EventReader, EventSystemBuilder, Events, ReadEvents};
+pub use crate::core::input::GamepadEvent;
+pub use crate::core::window::WindowId;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use winit::event_loop::EventLoopProxy;
+
+pub type WindowEvent<'a> = winit::event::WindowEvent<'a>;
+pub type VirtualKeyCode = winit::event::VirtualKeyCode;
+pub type KeyboardInput = winit::event::KeyboardInput;
+pub type DeviceEvent = winit::event::DeviceEvent;
+pub type MouseButton = winit::event::MouseButton;
+pub type ModifiersState = winit::event::ModifiersState;
+
+pub type EventChannel<T> = legion::event::Channel<T>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicationEvent {
+    Suspended,
+    Resumed,
+    Terminating,
+}
+
+#[derive(Debug)]
+pub enum Event<'a> {
+    Application(ApplicationEvent),
+    /// An event of the window with the given id. See the [`Windows`] resource.
+    ///
+    /// [`Windows`]: ../window/struct.Windows.html
+    Window(WindowId, WindowEvent<'a>),
+    /// Raw input from a device, not tied to a window, such as unaccelerated mouse motion.
+    Device(DeviceEvent),
+    Gamepad(GamepadEvent),
+    /// An event sent through an [`EventSender`](struct.EventSender.html).
+    User(UserEvent),
+}
+
+/// A value of any type sent to the scenes from another thread.
+///
+/// Scenes and plugins written independently send events of their own types through the
+/// same `EventSender`, so the type is only known when the event is read.
+pub struct UserEvent {
+    event: Box<dyn Any + Send>,
+    type_name: &'static str,
+}
+
+impl UserEvent {
+    pub fn new<T>(event: T) -> Self
+    where
+        T: Any + Send,
+    {
+        UserEvent {
+            event: Box::new(event),
+            type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// The name of the type of the value, for diagnostics.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn is<T>(&self) -> bool
+    where
+        T: Any + Send,
+    {
+        self.event.is::<T>()
+    }
+
+    pub fn downcast_ref<T>(&self) -> Option<&T>
+    where
+        T: Any + Send,
+    {
+        self.event.downcast_ref()
+    }
+
+    /// Takes the value out of the event, or gives the event back if it holds another type.
+    pub fn downcast<T>(self) -> std::result::Result<T, Self>
+    where
+        T: Any + Send,
+    {
+        let type_name = self.type_name;
+        self.event
+            .downcast()
+            .map(|event| *event)
+            .map_err(|event| UserEvent { event, type_name })
+    }
+}
+
+impl fmt::Debug for UserEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UserEvent({})", self.type_name)
+    }
+}
+
+enum SenderState {
+    /// The event loop has not started, or the application runs without one.
+    Queued(Vec<UserEvent>),
+    Running(EventLoopProxy<UserEvent>),
+    Stopped,
+}
+
+/// Sends events to the scenes' `handle_event` from any thread, waking the event loop.
+///
+/// Obtained from `ApplicationBuilder::event_sender` before the application runs. Events sent
+/// before the event loop starts are delivered once it does.
+#[derive(Clone)]
+pub struct EventSender {
+    state: Arc<Mutex<SenderState>>,
+}
+
+impl EventSender {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SenderState::Queued(Vec::new()))),
+        }
+    }
+
+    /// Sends `event` to the application. Gives the event back if the application has stopped.
+    pub fn send<T>(&self, event: T) -> std::result::Result<(), UserEvent>
+    where
+        T: Any + Send,
+    {
+        let event = UserEvent::new(event);
+        match &mut *self.state.lock().unwrap() {
+            SenderState::Queued(events) => {
+                events.push(event);
+                Ok(())
+            }
+            SenderState::Running(proxy) => proxy.send_event(event).map_err(|closed| closed.0),
+            SenderState::Stopped => Err(event),
+        }
+    }
+
+    /// Sends the queued events through `proxy` and every later event as well.
+    pub(crate) fn start(&self, proxy: EventLoopProxy<UserEvent>) {
+        let mut state = self.state.lock().unwrap();
+        if let SenderState::Queued(events) = &mut *state {
+            for event in events.drain(..) {
+                let _ = proxy.send_event(event);
+            }
+        }
+        *state = SenderState::Running(proxy);
+    }
+
+    /// The events queued while running without an event loop.
+    pub(crate) fn take_queued(&self) -> Vec<UserEvent> {
+        match &mut *self.state.lock().unwrap() {
+            SenderState::Queued(events) => mem::take(events),
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn stop(&self) {
+        *self.state.lock().unwrap() = SenderState::Stopped;
+    }
+}
+
+impl fmt::Debug for EventSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSender")
+    }
+}
@@ -0,0 +1,406 @@
+use super::InputState;
+use crate::core::error::{Error, Result};
+use crate::core::event::{MouseButton, VirtualKeyCode};
+use crate::core::serialization::format_of;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A key or mouse button that actions and axes can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Button {
+    fn is_down(self, input: &InputState) -> bool {
+        // Buttons tapped within a single frame count as down for that frame.
+        match self {
+            Button::Key(key) => input.is_key_held(key) || input.is_key_pressed(key),
+            Button::Mouse(button) => {
+                input.is_mouse_button_held(button) || input.is_mouse_button_pressed(button)
+            }
+        }
+    }
+}
+
+impl From<VirtualKeyCode> for Button {
+    fn from(key: VirtualKeyCode) -> Self {
+        Button::Key(key)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        Button::Mouse(button)
+    }
+}
+
+/// Buttons that must all be down at the same time. A chord of one button is a plain binding.
+pub type Chord = Vec<Button>;
+
+/// Binds an axis to a chord that pushes it towards 1 and a chord that pushes it towards -1.
+/// Either may be left empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisBinding {
+    pub positive: Chord,
+    pub negative: Chord,
+}
+
+/// The chords bound to every named action and axis, as stored in the bindings file.
+///
+/// ```yaml
+/// actions:
+///   jump:
+///     - [key: Space]
+///     - [mouse: Left]
+///   save:
+///     - [key: LControl, key: S]
+/// axes:
+///   move_x:
+///     - positive: [key: D]
+///       negative: [key: A]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub actions: BTreeMap<String, Vec<Chord>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Bindings {
+    /// Reads the bindings from `path`, in the format picked by its extension.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        format_of(path)?.parse(path, &contents)
+    }
+
+    /// Writes the bindings to `path`, in the format picked by its extension.
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = format_of(path)?.serialize(path, self)?;
+        fs::write(path, contents).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Adds `chord` to the chords that trigger `action`.
+    pub fn bind<S, C>(&mut self, action: S, chord: C)
+    where
+        S: Into<String>,
+        C: Into<Chord>,
+    {
+        self.actions
+            .entry(action.into())
+            .or_default()
+            .push(chord.into());
+    }
+
+    /// Adds a pair of chords that move `axis`.
+    pub fn bind_axis<S>(&mut self, axis: S, positive: Chord, negative: Chord)
+    where
+        S: Into<String>,
+    {
+        self.axes
+            .entry(axis.into())
+            .or_default()
+            .push(AxisBinding { positive, negative });
+    }
+
+    /// Replaces the chords of `action` with `chord`.
+    pub fn rebind<S, C>(&mut self, action: S, chord: C)
+    where
+        S: Into<String>,
+        C: Into<Chord>,
+    {
+        self.actions.insert(action.into(), vec![chord.into()]);
+    }
+
+    /// Removes every binding of `action`.
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ActionValue {
+    held: bool,
+    pressed: bool,
+    released: bool,
+}
+
+/// The state of the named actions and axes, evaluated from the `InputState` every frame
+/// before the scenes and systems update.
+///
+/// The bindings can be changed at runtime through [`bindings_mut`](#method.bindings_mut)
+/// and written back to the file they were loaded from with [`save`](#method.save).
+#[derive(Debug, Clone)]
+pub struct ActionState {
+    bindings: Bindings,
+    path: PathBuf,
+    actions: HashMap<String, ActionValue>,
+    axes: HashMap<String, f32>,
+    last_reload_error: Option<Arc<Error>>,
+}
+
+impl ActionState {
+    /// Creates the state of `bindings`, which are saved to `path`.
+    pub fn new<P>(bindings: Bindings, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            bindings,
+            path: path.into(),
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+            last_reload_error: None,
+        }
+    }
+
+    /// Loads the bindings at `path`, or uses `defaults` if the file does not exist.
+    pub fn load<P>(path: P, defaults: Bindings) -> Result<Self>
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        let bindings = match Bindings::load(&path) {
+            Ok(bindings) => bindings,
+            Err(Error::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => defaults,
+            Err(error) => return Err(error),
+        };
+        Ok(Self::new(bindings, path))
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    /// The file the bindings are saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the current bindings to their file.
+    pub fn save(&self) -> Result<()> {
+        self.bindings.save(&self.path)
+    }
+
+    /// Whether a chord bound to `action` is down.
+    pub fn is_held(&self, action: &str) -> bool {
+        self.action(action).held
+    }
+
+    /// Whether `action` started this frame.
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.action(action).pressed
+    }
+
+    /// Whether `action` ended this frame.
+    pub fn is_released(&self, action: &str) -> bool {
+        self.action(action).released
+    }
+
+    /// The value of `axis`, between -1 and 1. Unknown axes are 0.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.get(axis).copied().unwrap_or_default()
+    }
+
+    fn action(&self, action: &str) -> ActionValue {
+        self.actions.get(action).copied().unwrap_or_default()
+    }
+
+    /// Why the bindings file could not be reloaded after it last changed, if it could not.
+    /// The previous bindings stay in use until the file is fixed.
+    pub fn last_reload_error(&self) -> Option<&Error> {
+        self.last_reload_error.as_deref()
+    }
+
+    /// Replaces the bindings with the ones in their file. Bindings changed at runtime and not
+    /// saved are lost.
+    pub(crate) fn reload(&mut self) {
+        match Bindings::load(&self.path) {
+            Ok(bindings) => {
+                self.bindings = bindings;
+                self.last_reload_error = None;
+            }
+            Err(error) => self.last_reload_error = Some(Arc::new(error)),
+        }
+    }
+
+    /// Evaluates the bindings against the input of this frame.
+    pub(crate) fn update(&mut self, input: &InputState) {
+        let is_down =
+            |chord: &Chord| !chord.is_empty() && chord.iter().all(|button| button.is_down(input));
+
+        let mut actions = HashMap::with_capacity(self.bindings.actions.len());
+        for (name, chords) in &self.bindings.actions {
+            let held = chords.iter().any(is_down);
+            let was_held = self.is_held(name);
+            let value = ActionValue {
+                held,
+                pressed: held && !was_held,
+                released: was_held && !held,
+            };
+            actions.insert(name.clone(), value);
+        }
+
+        // Actions that were unbound while held are released.
+        for (name, value) in &self.actions {
+            if value.held && !actions.contains_key(name) {
+                let released = ActionValue {
+                    released: true,
+                    ..ActionValue::default()
+                };
+                actions.insert(name.clone(), released);
+            }
+        }
+        self.actions = actions;
+
+        self.axes = self
+            .bindings
+            .axes
+            .iter()
+            .map(|(name, bindings)| {
+                let value = bindings
+                    .iter()
+                    .map(|binding| {
+                        let positive = if is_down(&binding.positive) { 1.0 } else { 0.0 };
+                        let negative = if is_down(&binding.negative) { 1.0 } else { 0.0 };
+                        positive - negative
+                    })
+                    .sum::<f32>();
+                (name.clone(), value.clamp(-1.0, 1.0))
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::{KeyboardInput, ModifiersState, WindowEvent};
+    use winit::event::{DeviceId, ElementState};
+
+    #[allow(deprecated)]
+    fn key(input: &mut InputState, key: VirtualKeyCode, state: ElementState) {
+        input.handle_window_event(&WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        });
+    }
+
+    #[test]
+    fn evaluates_chords_and_axes() {
+        let mut bindings = Bindings::default();
+        bindings.bind(
+            "save",
+            vec![VirtualKeyCode::LControl.into(), VirtualKeyCode::S.into()],
+        );
+        bindings.bind_axis(
+            "move_x",
+            vec![VirtualKeyCode::D.into()],
+            vec![VirtualKeyCode::A.into()],
+        );
+        let mut actions = ActionState::new(bindings, "bindings.yml");
+        let mut input = InputState::default();
+
+        key(&mut input, VirtualKeyCode::S, ElementState::Pressed);
+        key(&mut input, VirtualKeyCode::A, ElementState::Pressed);
+        actions.update(&input);
+        assert!(!actions.is_held("save"));
+        assert_eq!(actions.axis("move_x"), -1.0);
+
+        input.end_frame();
+        key(&mut input, VirtualKeyCode::LControl, ElementState::Pressed);
+        key(&mut input, VirtualKeyCode::D, ElementState::Pressed);
+        actions.update(&input);
+        assert!(actions.is_pressed("save"));
+        assert_eq!(actions.axis("move_x"), 0.0);
+
+        input.end_frame();
+        actions.update(&input);
+        assert!(actions.is_held("save"));
+        assert!(!actions.is_pressed("save"));
+
+        input.end_frame();
+        actions
+            .bindings_mut()
+            .rebind("save", vec![VirtualKeyCode::F5.into()]);
+        actions.update(&input);
+        assert!(actions.is_released("save"));
+        assert!(!actions.is_held("save"));
+    }
+
+    #[test]
+    fn saves_and_loads_bindings() {
+        let path = std::env::temp_dir().join(format!("crius-bindings-{}.yml", std::process::id()));
+
+        let mut bindings = Bindings::default();
+        bindings.bind("jump", vec![VirtualKeyCode::Space.into()]);
+        bindings.bind("jump", vec![MouseButton::Other(4).into()]);
+        ActionState::new(bindings.clone(), &path).save().unwrap();
+
+        let loaded = ActionState::load(&path, Bindings::default());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap().bindings(), &bindings);
+        let missing = ActionState::load(&path, bindings.clone()).unwrap();
+        assert_eq!(missing.bindings(), &bindings);
+    }
+
+    #[test]
+    fn keeps_the_bindings_when_reloading_fails() {
+        let path = std::env::temp_dir().join(format!(
+            "crius-reloaded-bindings-{}.yml",
+            std::process::id()
+        ));
+
+        let mut bindings = Bindings::default();
+        bindings.bind("jump", vec![VirtualKeyCode::Space.into()]);
+        let mut actions = ActionState::new(bindings.clone(), &path);
+        actions.save().unwrap();
+
+        fs::write(&path, "actions: [").unwrap();
+        actions.reload();
+        assert_eq!(actions.bindings(), &bindings);
+        assert!(matches!(
+            actions.last_reload_error(),
+            Some(Error::Parse { .. })
+        ));
+
+        actions.bindings_mut().unbind("jump");
+        bindings.save(&path).unwrap();
+        actions.reload();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(actions.bindings(), &bindings);
+        assert!(actions.last_reload_error().is_none());
+    }
+}
@@ -0,0 +1,260 @@
+use super::Buttons;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Identifies a connected gamepad. Ids are not reused while the application runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GamepadId(pub usize);
+
+/// The buttons of a gamepad, named by their position on the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// The analog axes of a gamepad. Sticks range from -1 to 1, up and right being positive.
+/// Triggers range from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadEventKind {
+    Connected { name: String },
+    Disconnected,
+    ButtonPressed(GamepadButton),
+    ButtonReleased(GamepadButton),
+    AxisChanged(GamepadAxis, f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GamepadEvent {
+    pub id: GamepadId,
+    pub kind: GamepadEventKind,
+}
+
+/// A source of gamepad events, such as the platform's controller API.
+///
+/// The application polls its backend once per frame, before the frame updates, and hands
+/// the events to the `GamepadState` resource and then to the scenes.
+pub trait GamepadBackend: Send + 'static {
+    /// Returns the events that happened since the previous poll, oldest first.
+    fn poll(&mut self) -> Vec<GamepadEvent>;
+}
+
+/// A gamepad backend driven by code, for tests and simulations.
+///
+/// Clones share their gamepads, so a clone kept after handing the backend to the
+/// application can connect gamepads and press their buttons.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualGamepads {
+    inner: Arc<Mutex<VirtualGamepadsInner>>,
+}
+
+#[derive(Debug, Default)]
+struct VirtualGamepadsInner {
+    events: Vec<GamepadEvent>,
+    next_id: usize,
+}
+
+impl VirtualGamepads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a new gamepad named `name`.
+    pub fn connect<S>(&self, name: S) -> GamepadId
+    where
+        S: Into<String>,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let id = GamepadId(inner.next_id);
+        inner.next_id += 1;
+        inner.events.push(GamepadEvent {
+            id,
+            kind: GamepadEventKind::Connected { name: name.into() },
+        });
+        id
+    }
+
+    pub fn disconnect(&self, id: GamepadId) {
+        self.push(id, GamepadEventKind::Disconnected);
+    }
+
+    pub fn press(&self, id: GamepadId, button: GamepadButton) {
+        self.push(id, GamepadEventKind::ButtonPressed(button));
+    }
+
+    pub fn release(&self, id: GamepadId, button: GamepadButton) {
+        self.push(id, GamepadEventKind::ButtonReleased(button));
+    }
+
+    pub fn set_axis(&self, id: GamepadId, axis: GamepadAxis, value: f32) {
+        self.push(id, GamepadEventKind::AxisChanged(axis, value));
+    }
+
+    fn push(&self, id: GamepadId, kind: GamepadEventKind) {
+        self.inner
+            .lock()
+            .unwrap()
+            .events
+            .push(GamepadEvent { id, kind });
+    }
+}
+
+impl GamepadBackend for VirtualGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        std::mem::take(&mut self.inner.lock().unwrap().events)
+    }
+}
+
+/// The state of a connected gamepad.
+#[derive(Debug, Clone)]
+pub struct Gamepad {
+    name: String,
+    buttons: Buttons<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Gamepad {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_button_held(&self, button: GamepadButton) -> bool {
+        self.buttons.held.contains(&button)
+    }
+
+    /// Whether `button` was pressed this frame.
+    pub fn is_button_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons.pressed.contains(&button)
+    }
+
+    /// Whether `button` was released this frame.
+    pub fn is_button_released(&self, button: GamepadButton) -> bool {
+        self.buttons.released.contains(&button)
+    }
+
+    /// The value of `axis`, 0 until the gamepad reports it.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or_default()
+    }
+}
+
+/// The connected gamepads, kept up to date from the application's `GamepadBackend`.
+#[derive(Debug, Clone, Default)]
+pub struct GamepadState {
+    gamepads: BTreeMap<GamepadId, Gamepad>,
+}
+
+impl GamepadState {
+    /// The ids of the connected gamepads, in the order they connected.
+    pub fn connected(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.gamepads.keys().copied()
+    }
+
+    pub fn gamepad(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(&id)
+    }
+
+    pub(crate) fn handle_event(&mut self, event: &GamepadEvent) {
+        if let GamepadEventKind::Connected { name } = &event.kind {
+            let gamepad = Gamepad {
+                name: name.clone(),
+                buttons: Buttons::default(),
+                axes: HashMap::new(),
+            };
+            self.gamepads.insert(event.id, gamepad);
+            return;
+        }
+
+        let gamepad = match self.gamepads.get_mut(&event.id) {
+            Some(gamepad) => gamepad,
+            None => return,
+        };
+        match event.kind {
+            GamepadEventKind::Disconnected => {
+                self.gamepads.remove(&event.id);
+            }
+            GamepadEventKind::ButtonPressed(button) => gamepad.buttons.press(button),
+            GamepadEventKind::ButtonReleased(button) => gamepad.buttons.release(button),
+            GamepadEventKind::AxisChanged(axis, value) => {
+                gamepad.axes.insert(axis, value);
+            }
+            GamepadEventKind::Connected { .. } => {}
+        }
+    }
+
+    /// Clears the state that only lasts for one frame.
+    pub(crate) fn end_frame(&mut self) {
+        for gamepad in self.gamepads.values_mut() {
+            gamepad.buttons.end_frame();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_gamepads_from_a_virtual_backend() {
+        let mut backend = VirtualGamepads::new();
+        let mut state = GamepadState::default();
+        let apply = |backend: &mut VirtualGamepads, state: &mut GamepadState| {
+            state.end_frame();
+            for event in backend.poll() {
+                state.handle_event(&event);
+            }
+        };
+
+        let first = backend.connect("First");
+        let second = backend.connect("Second");
+        backend.press(first, GamepadButton::South);
+        backend.set_axis(second, GamepadAxis::LeftStickX, -0.5);
+        apply(&mut backend, &mut state);
+
+        assert_eq!(state.connected().collect::<Vec<_>>(), vec![first, second]);
+        let gamepad = state.gamepad(first).unwrap();
+        assert_eq!(gamepad.name(), "First");
+        assert!(gamepad.is_button_pressed(GamepadButton::South));
+        assert_eq!(
+            state.gamepad(second).unwrap().axis(GamepadAxis::LeftStickX),
+            -0.5
+        );
+
+        backend.disconnect(second);
+        apply(&mut backend, &mut state);
+
+        let gamepad = state.gamepad(first).unwrap();
+        assert!(gamepad.is_button_held(GamepadButton::South));
+        assert!(!gamepad.is_button_pressed(GamepadButton::South));
+        assert!(state.gamepad(second).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! The default gamepad backend, reading controllers through `gilrs`.
+
+use super::{
+    GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent, GamepadEventKind, GamepadId,
+};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use std::collections::HashMap;
+
+/// Reads the gamepads connected to the machine.
+///
+/// Used by the application when no other backend is given, unless the `gilrs` feature is
+/// disabled. Analog triggers are reported as the trigger axes rather than as buttons.
+pub struct GilrsGamepads {
+    gilrs: Gilrs,
+    /// The ids handed out for the gamepads gilrs knows. Reconnected gamepads get a new id.
+    ids: HashMap<gilrs::GamepadId, GamepadId>,
+    next_id: usize,
+    /// The gamepads connected before the first poll, which gilrs does not send events for.
+    pending: Vec<GamepadEvent>,
+}
+
+impl GilrsGamepads {
+    /// Opens the platform's controller API. Returns `None` where it is unavailable.
+    pub fn new() -> Option<Self> {
+        let gilrs = Gilrs::new().ok()?;
+        let mut gamepads = Self {
+            gilrs,
+            ids: HashMap::new(),
+            next_id: 0,
+            pending: Vec::new(),
+        };
+
+        let connected = gamepads
+            .gilrs
+            .gamepads()
+            .map(|(id, gamepad)| (id, gamepad.name().to_string()))
+            .collect::<Vec<_>>();
+        for (id, name) in connected {
+            let event = gamepads.connect(id, name);
+            gamepads.pending.push(event);
+        }
+        Some(gamepads)
+    }
+
+    fn connect(&mut self, id: gilrs::GamepadId, name: String) -> GamepadEvent {
+        let gamepad = GamepadId(self.next_id);
+        self.next_id += 1;
+        self.ids.insert(id, gamepad);
+        GamepadEvent {
+            id: gamepad,
+            kind: GamepadEventKind::Connected { name },
+        }
+    }
+
+    fn translate(&mut self, id: gilrs::GamepadId, event: EventType) -> Option<GamepadEvent> {
+        if let EventType::Connected = event {
+            let name = self.gilrs.gamepad(id).name().to_string();
+            return Some(self.connect(id, name));
+        }
+
+        let gamepad = *self.ids.get(&id)?;
+        let kind = match event {
+            EventType::Disconnected => {
+                self.ids.remove(&id);
+                GamepadEventKind::Disconnected
+            }
+            EventType::ButtonChanged(button, value, _) => {
+                GamepadEventKind::AxisChanged(trigger_axis(button)?, value)
+            }
+            EventType::ButtonPressed(button, _) => {
+                GamepadEventKind::ButtonPressed(gamepad_button(button)?)
+            }
+            EventType::ButtonReleased(button, _) => {
+                GamepadEventKind::ButtonReleased(gamepad_button(button)?)
+            }
+            EventType::AxisChanged(axis, value, _) => {
+                GamepadEventKind::AxisChanged(gamepad_axis(axis)?, value)
+            }
+            _ => return None,
+        };
+        Some(GamepadEvent { id: gamepad, kind })
+    }
+}
+
+impl GamepadBackend for GilrsGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        let mut events = std::mem::take(&mut self.pending);
+        while let Some(event) = self.gilrs.next_event() {
+            events.extend(self.translate(event.id, event.event));
+        }
+        events
+    }
+}
+
+fn gamepad_button(button: Button) -> Option<GamepadButton> {
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftThumb,
+        Button::RightThumb => GamepadButton::RightThumb,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+fn trigger_axis(button: Button) -> Option<GamepadAxis> {
+    match button {
+        Button::LeftTrigger2 => Some(GamepadAxis::LeftTrigger),
+        Button::RightTrigger2 => Some(GamepadAxis::RightTrigger),
+        _ => None,
+    }
+}
+
+fn gamepad_axis(axis: Axis) -> Option<GamepadAxis> {
+    match axis {
+        Axis::LeftStickX => Some(GamepadAxis::LeftStickX),
+        Axis::LeftStickY => Some(GamepadAxis::LeftStickY),
+        Axis::RightStickX => Some(GamepadAxis::RightStickX),
+        Axis::RightStickY => Some(GamepadAxis::RightStickY),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_analog_triggers_as_axes() {
+        assert_eq!(
+            gamepad_button(Button::LeftTrigger),
+            Some(GamepadButton::LeftBumper)
+        );
+        assert_eq!(
+            gamepad_button(Button::RightTrigger2),
+            Some(GamepadButton::RightTrigger)
+        );
+        assert_eq!(
+            trigger_axis(Button::RightTrigger2),
+            Some(GamepadAxis::RightTrigger)
+        );
+        assert_eq!(trigger_axis(Button::South), None);
+        assert_eq!(gamepad_axis(Axis::DPadX), None);
+    }
+}
@@ -0,0 +1,326 @@
+//! The state of the keyboard and the mouse.
+//!
+//! The application keeps an [`InputState`](struct.InputState.html) in `world.resources`. It is
+//! updated from the window events before they are handed to the scenes, so systems and scenes
+//! can read the input of the current frame without handling events themselves.
+//!
+//! Named actions and axes are bound to keys and mouse buttons in the `bindings.yml` file next to
+//! the settings file. They are evaluated into the [`ActionState`](struct.ActionState.html)
+//! resource every frame, before the scenes and systems update.
+//!
+//! Gamepads are read from the [`GamepadBackend`](trait.GamepadBackend.html) added with
+//! `ApplicationBuilder::with_gamepad_backend`, or from
+//! [`GilrsGamepads`](struct.GilrsGamepads.html) when none is added and the `gilrs` feature is
+//! enabled. The feature is off by default, since gilrs needs libudev and its pkg-config file
+//! (`libudev-dev` on Debian and Ubuntu) to build on Linux. Gamepad events update the
+//! [`GamepadState`](struct.GamepadState.html) resource and are then handed to the scenes as
+//! `Event::Gamepad`.
+
+mod actions;
+mod gamepad;
+#[cfg(feature = "gilrs")]
+mod gilrs;
+
+pub use self::actions::{ActionState, AxisBinding, Bindings, Button, Chord};
+pub use self::gamepad::{
+    Gamepad, GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent, GamepadEventKind, GamepadId,
+    GamepadState, VirtualGamepads,
+};
+#[cfg(feature = "gilrs")]
+pub use self::gilrs::GilrsGamepads;
+
+use crate::core::event::{DeviceEvent, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent};
+use nalgebra::{Point2, Vector2};
+use std::collections::HashSet;
+use std::hash::Hash;
+use winit::event::{ElementState, MouseScrollDelta};
+
+/// The held, pressed and released state of a set of buttons.
+#[derive(Debug, Clone)]
+struct Buttons<T> {
+    held: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T> Default for Buttons<T> {
+    fn default() -> Self {
+        Self {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+}
+
+impl<T> Buttons<T>
+where
+    T: Copy + Eq + Hash,
+{
+    fn press(&mut self, button: T) {
+        // Key repeats arrive as presses of a held key.
+        if self.held.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    fn release(&mut self, button: T) {
+        if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+/// The keyboard and mouse input of the application's windows.
+///
+/// Keys and buttons that were pressed and released within the same frame are reported as
+/// both pressed and released, but not as held.
+#[derive(Debug, Clone)]
+pub struct InputState {
+    keys: Buttons<VirtualKeyCode>,
+    mouse_buttons: Buttons<MouseButton>,
+    cursor_position: Option<Point2<f64>>,
+    cursor_delta: Vector2<f64>,
+    mouse_motion: Vector2<f64>,
+    scroll_lines: Vector2<f32>,
+    scroll_pixels: Vector2<f64>,
+    modifiers: ModifiersState,
+    text: String,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            keys: Buttons::default(),
+            mouse_buttons: Buttons::default(),
+            cursor_position: None,
+            cursor_delta: Vector2::zeros(),
+            mouse_motion: Vector2::zeros(),
+            scroll_lines: Vector2::zeros(),
+            scroll_pixels: Vector2::zeros(),
+            modifiers: ModifiersState::empty(),
+            text: String::new(),
+        }
+    }
+}
+
+impl InputState {
+    pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.held.contains(&key)
+    }
+
+    /// Whether `key` was pressed this frame.
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys.pressed.contains(&key)
+    }
+
+    /// Whether `key` was released this frame.
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys.released.contains(&key)
+    }
+
+    pub fn keys_held(&self) -> impl Iterator<Item = VirtualKeyCode> + '_ {
+        self.keys.held.iter().copied()
+    }
+
+    pub fn is_mouse_button_held(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.held.contains(&button)
+    }
+
+    /// Whether `button` was pressed this frame.
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed.contains(&button)
+    }
+
+    /// Whether `button` was released this frame.
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.released.contains(&button)
+    }
+
+    /// The position of the cursor in physical pixels from the top left corner of the window,
+    /// or `None` if the cursor is outside of the window.
+    pub fn cursor_position(&self) -> Option<Point2<f64>> {
+        self.cursor_position
+    }
+
+    /// How far the cursor moved within the window this frame, in physical pixels.
+    pub fn cursor_delta(&self) -> Vector2<f64> {
+        self.cursor_delta
+    }
+
+    /// The raw motion of the mouse this frame, in platform units. Unlike the cursor delta it
+    /// is not accelerated and keeps changing while the cursor is grabbed or at an edge.
+    pub fn mouse_motion(&self) -> Vector2<f64> {
+        self.mouse_motion
+    }
+
+    /// The lines and columns scrolled this frame by mouse wheels.
+    pub fn scroll_lines(&self) -> Vector2<f32> {
+        self.scroll_lines
+    }
+
+    /// The pixels scrolled this frame by touchpads.
+    pub fn scroll_pixels(&self) -> Vector2<f64> {
+        self.scroll_pixels
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    /// The text typed this frame, without control characters.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.keys.press(key),
+                        ElementState::Released => self.keys.release(key),
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => self.mouse_buttons.press(*button),
+                ElementState::Released => self.mouse_buttons.release(*button),
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Point2::new(position.x, position.y);
+                if let Some(previous) = self.cursor_position {
+                    self.cursor_delta += position - previous;
+                }
+                self.cursor_position = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => self.scroll_lines += Vector2::new(*x, *y),
+                MouseScrollDelta::PixelDelta(delta) => {
+                    self.scroll_pixels += Vector2::new(delta.x, delta.y)
+                }
+            },
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::ReceivedCharacter(character) if !character.is_control() => {
+                self.text.push(*character)
+            }
+            WindowEvent::Focused(false) => {
+                // The window will not be told about releases while it is out of focus.
+                self.keys.release_all();
+                self.mouse_buttons.release_all();
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            self.mouse_motion += Vector2::new(*x, *y);
+        }
+    }
+
+    /// Clears the state that only lasts for one frame.
+    pub(crate) fn end_frame(&mut self) {
+        self.keys.end_frame();
+        self.mouse_buttons.end_frame();
+        self.cursor_delta = Vector2::zeros();
+        self.mouse_motion = Vector2::zeros();
+        self.scroll_lines = Vector2::zeros();
+        self.scroll_pixels = Vector2::zeros();
+        self.text.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::KeyboardInput;
+    use winit::dpi::PhysicalPosition;
+    use winit::event::DeviceId;
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn cursor_moved(x: f64, y: f64) -> WindowEvent<'static> {
+        WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[test]
+    fn tracks_held_pressed_and_released_keys_per_frame() {
+        let mut input = InputState::default();
+        input.handle_window_event(&key(VirtualKeyCode::A, ElementState::Pressed));
+        input.handle_window_event(&key(VirtualKeyCode::B, ElementState::Pressed));
+        input.handle_window_event(&key(VirtualKeyCode::B, ElementState::Released));
+
+        assert!(input.is_key_held(VirtualKeyCode::A));
+        assert!(input.is_key_pressed(VirtualKeyCode::A));
+        assert!(!input.is_key_held(VirtualKeyCode::B));
+        assert!(input.is_key_pressed(VirtualKeyCode::B));
+        assert!(input.is_key_released(VirtualKeyCode::B));
+
+        input.end_frame();
+        // A key repeat does not press the key again.
+        input.handle_window_event(&key(VirtualKeyCode::A, ElementState::Pressed));
+
+        assert!(input.is_key_held(VirtualKeyCode::A));
+        assert!(!input.is_key_pressed(VirtualKeyCode::A));
+        assert!(!input.is_key_released(VirtualKeyCode::B));
+
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert!(input.is_key_released(VirtualKeyCode::A));
+        assert_eq!(input.keys_held().count(), 0);
+    }
+
+    #[test]
+    fn accumulates_cursor_movement_and_text() {
+        let mut input = InputState::default();
+        input.handle_window_event(&cursor_moved(10.0, 10.0));
+        assert_eq!(input.cursor_delta(), Vector2::zeros());
+
+        input.handle_window_event(&cursor_moved(12.0, 15.0));
+        input.handle_window_event(&cursor_moved(13.0, 14.0));
+        input.handle_window_event(&WindowEvent::ReceivedCharacter('h'));
+        input.handle_window_event(&WindowEvent::ReceivedCharacter('\u{8}'));
+        input.handle_window_event(&WindowEvent::ReceivedCharacter('i'));
+
+        input.handle_device_event(&DeviceEvent::MouseMotion { delta: (-2.0, 1.0) });
+
+        assert_eq!(input.cursor_position(), Some(Point2::new(13.0, 14.0)));
+        assert_eq!(input.cursor_delta(), Vector2::new(3.0, 4.0));
+        assert_eq!(input.mouse_motion(), Vector2::new(-2.0, 1.0));
+        assert_eq!(input.text(), "hi");
+
+        input.end_frame();
+        assert_eq!(input.cursor_delta(), Vector2::zeros());
+        assert_eq!(input.mouse_motion(), Vector2::zeros());
+        assert_eq!(input.text(), "");
+        assert_eq!(input.cursor_position(), Some(Point2::new(13.0, 14.0)));
+    }
+}
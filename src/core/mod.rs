@@ -1,5 +1,20 @@
 pub mod application;
-pub mod scene;
+pub mod asset;
+pub mod error;
 pub mod event;
+pub mod input;
+pub mod pacing;
+pub mod plugin;
+pub mod prefab;
+pub mod registry;
+pub mod replay;
+pub mod scene;
+pub mod schedule;
+pub mod serialization;
+pub mod time;
+pub mod timestep;
+pub mod vfs;
+pub mod watcher;
+pub mod window;
 
 pub use legion as ecs;
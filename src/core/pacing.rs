@@ -0,0 +1,228 @@
+//! Pacing the frames of the windowed main loop.
+//!
+//! The application keeps a [`FramePacing`](struct.FramePacing.html) resource built from
+//! `Settings::frame_pacing`. Scenes change it at runtime through
+//! [`Context::set_frame_pacing`](../scene/struct.Context.html#method.set_frame_pacing), and
+//! the event loop waits with `ControlFlow::Wait` or `ControlFlow::WaitUntil` between frames
+//! instead of spinning when the settings allow it.
+
+use crate::core::application::settings::{FramePacingSettings, PacingMode};
+use crate::core::error::Result;
+use crate::core::event::{WindowEvent, WindowId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use winit::event_loop::ControlFlow;
+
+/// How the main loop runs frames with the current settings and window state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pace {
+    Continuous,
+    OnEvents,
+    /// At most once per interval, ignoring earlier events.
+    Capped(Duration),
+    /// Once per interval, or earlier when events arrive.
+    Timer(Duration),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WindowState {
+    focused: bool,
+    minimized: bool,
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        Self {
+            focused: true,
+            minimized: false,
+        }
+    }
+}
+
+/// The frame pacing settings in use and the window state they depend on.
+#[derive(Debug, Clone)]
+pub struct FramePacing {
+    settings: FramePacingSettings,
+    windows: HashMap<WindowId, WindowState>,
+}
+
+impl FramePacing {
+    pub fn new(settings: FramePacingSettings) -> Self {
+        Self {
+            settings,
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn settings(&self) -> FramePacingSettings {
+        self.settings
+    }
+
+    /// Uses `settings` from the next frame on, if they are valid.
+    pub fn set_settings(&mut self, settings: FramePacingSettings) -> Result<()> {
+        settings.validate()?;
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Whether every window is unfocused or minimized, so that `background_fps` applies.
+    /// Windows count as focused until they report otherwise.
+    pub fn is_in_background(&self) -> bool {
+        !self.windows.is_empty()
+            && self
+                .windows
+                .values()
+                .all(|window| !window.focused || window.minimized)
+    }
+
+    pub(crate) fn handle_window_event(&mut self, id: WindowId, event: &WindowEvent) {
+        let window = self.windows.entry(id).or_default();
+        match event {
+            WindowEvent::Focused(focused) => window.focused = *focused,
+            // Minimized windows report an empty size on the platforms that report anything.
+            WindowEvent::Resized(size) => window.minimized = size.width == 0 || size.height == 0,
+            _ => {}
+        }
+    }
+
+    /// Forgets the state of a closed window.
+    pub(crate) fn remove_window(&mut self, id: WindowId) {
+        self.windows.remove(&id);
+    }
+
+    /// Whether a frame should run at `now`, the previous one having started at `last_frame`.
+    pub(crate) fn is_frame_due(&self, last_frame: Instant, now: Instant) -> bool {
+        match self.pace() {
+            Pace::Capped(interval) => now >= last_frame + interval,
+            Pace::Continuous | Pace::OnEvents | Pace::Timer(_) => true,
+        }
+    }
+
+    /// How the event loop waits for the frame after the one started at `last_frame`.
+    pub(crate) fn control_flow(&self, last_frame: Instant) -> ControlFlow {
+        match self.pace() {
+            Pace::Continuous => ControlFlow::Poll,
+            Pace::OnEvents => ControlFlow::Wait,
+            Pace::Capped(interval) | Pace::Timer(interval) => {
+                ControlFlow::WaitUntil(last_frame + interval)
+            }
+        }
+    }
+
+    fn pace(&self) -> Pace {
+        let interval = |fps: f64| Duration::from_secs_f64(1.0 / fps);
+
+        if self.is_in_background() && self.settings.mode != PacingMode::Wait {
+            if let Some(fps) = self.settings.background_fps {
+                return Pace::Capped(interval(fps));
+            }
+        }
+
+        match (self.settings.mode, self.settings.max_fps) {
+            (PacingMode::Poll, None) => Pace::Continuous,
+            (PacingMode::Poll, Some(fps)) => Pace::Capped(interval(fps)),
+            (PacingMode::Wait, _) | (PacingMode::WaitUntil, None) => Pace::OnEvents,
+            (PacingMode::WaitUntil, Some(fps)) => Pace::Timer(interval(fps)),
+        }
+    }
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        Self::new(FramePacingSettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::window::Windows;
+    use winit::dpi::PhysicalSize;
+
+    #[test]
+    fn caps_the_frame_rate_and_slows_down_in_the_background() {
+        let mut pacing = FramePacing::new(FramePacingSettings {
+            mode: PacingMode::Poll,
+            max_fps: Some(50.0),
+            background_fps: Some(5.0),
+        });
+        let start = Instant::now();
+        let window = Windows::default().insert_placeholder("main".to_string());
+
+        assert!(!pacing.is_frame_due(start, start + Duration::from_millis(10)));
+        assert!(pacing.is_frame_due(start, start + Duration::from_millis(20)));
+        assert_eq!(
+            pacing.control_flow(start),
+            ControlFlow::WaitUntil(start + Duration::from_millis(20))
+        );
+
+        pacing.handle_window_event(window, &WindowEvent::Resized(PhysicalSize::new(0, 0)));
+        assert!(pacing.is_in_background());
+        assert!(!pacing.is_frame_due(start, start + Duration::from_millis(100)));
+        assert_eq!(
+            pacing.control_flow(start),
+            ControlFlow::WaitUntil(start + Duration::from_millis(200))
+        );
+
+        pacing.handle_window_event(window, &WindowEvent::Resized(PhysicalSize::new(800, 600)));
+        pacing.handle_window_event(window, &WindowEvent::Focused(false));
+        assert!(pacing.is_in_background());
+        pacing.handle_window_event(window, &WindowEvent::Focused(true));
+        assert!(!pacing.is_in_background());
+    }
+
+    #[test]
+    fn runs_in_the_background_only_when_every_window_is() {
+        let mut pacing = FramePacing::default();
+        let mut windows = Windows::default();
+        let viewport = windows.insert_placeholder("viewport".to_string());
+        let inspector = windows.insert_placeholder("inspector".to_string());
+
+        pacing.handle_window_event(viewport, &WindowEvent::Focused(true));
+        pacing.handle_window_event(inspector, &WindowEvent::Focused(false));
+        pacing.handle_window_event(inspector, &WindowEvent::Resized(PhysicalSize::new(0, 0)));
+        assert!(!pacing.is_in_background());
+
+        pacing.handle_window_event(viewport, &WindowEvent::Focused(false));
+        assert!(pacing.is_in_background());
+
+        pacing.remove_window(viewport);
+        assert!(pacing.is_in_background());
+        pacing.remove_window(inspector);
+        assert!(!pacing.is_in_background());
+    }
+
+    #[test]
+    fn waits_for_events() {
+        let mut pacing = FramePacing::default();
+        let start = Instant::now();
+        assert_eq!(pacing.control_flow(start), ControlFlow::Poll);
+
+        let wait = FramePacingSettings {
+            mode: PacingMode::Wait,
+            ..FramePacingSettings::default()
+        };
+        pacing.set_settings(wait).unwrap();
+        assert!(pacing.is_frame_due(start, start));
+        assert_eq!(pacing.control_flow(start), ControlFlow::Wait);
+
+        let wait_until = FramePacingSettings {
+            mode: PacingMode::WaitUntil,
+            ..wait
+        };
+        assert!(pacing.set_settings(wait_until).is_err());
+        assert_eq!(pacing.settings(), wait);
+
+        pacing
+            .set_settings(FramePacingSettings {
+                max_fps: Some(10.0),
+                ..wait_until
+            })
+            .unwrap();
+        assert!(pacing.is_frame_due(start, start));
+        assert_eq!(
+            pacing.control_flow(start),
+            ControlFlow::WaitUntil(start + Duration::from_millis(100))
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Reusable extensions of the application, such as an audio or an input layer.
+//!
+//! A [`Plugin`](trait.Plugin.html) adds its resources, systems, event types and settings to
+//! the `ApplicationBuilder` it is handed, so that a crate can ship it as a single
+//! `ApplicationBuilder::with_plugin` call instead of a builder chain to copy.
+//!
+//! Plugins are built when the application is, every plugin after the plugins it depends on
+//! and otherwise in the order they were added. Plugins added by other plugins are built
+//! after them, and plugins added earlier may depend on them. Adding the same plugin twice,
+//! depending on a plugin that was not added and depending on each other in a cycle make
+//! `ApplicationBuilder::build` fail with `Error::Plugin`.
+
+use crate::core::application::ApplicationBuilder;
+use crate::core::error::{Error, Result};
+use std::any::{type_name, TypeId};
+
+pub trait Plugin: 'static {
+    /// Adds the plugin to `builder`.
+    fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder;
+
+    /// The plugins that must be built before this one.
+    fn dependencies(&self) -> Vec<Dependency> {
+        Vec::new()
+    }
+}
+
+/// A plugin another plugin depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl Dependency {
+    pub fn on<P>() -> Self
+    where
+        P: Plugin,
+    {
+        Self {
+            id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+        }
+    }
+}
+
+/// A plugin added to an `ApplicationBuilder`, not built yet.
+pub(crate) struct PendingPlugin {
+    id: TypeId,
+    name: &'static str,
+    plugin: Box<dyn Plugin>,
+}
+
+impl PendingPlugin {
+    pub(crate) fn new<P>(plugin: P) -> Self
+    where
+        P: Plugin,
+    {
+        Self {
+            id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+            plugin: Box::new(plugin),
+        }
+    }
+
+    pub(crate) fn id(&self) -> TypeId {
+        self.id
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+        self.plugin.build(builder)
+    }
+}
+
+/// Orders the plugins of `pending` that can be built now so that every plugin comes after
+/// its dependencies, keeping the order they were added in otherwise. Dependencies on plugins
+/// in `built` are met already.
+///
+/// Plugins that depend, directly or not, on a plugin that was not added yet are returned
+/// second, to be ordered again once the plugins built now have added theirs. They are
+/// reported missing when no plugin can be built at all.
+pub(crate) fn order(
+    pending: Vec<PendingPlugin>,
+    built: &[TypeId],
+) -> Result<(Vec<PendingPlugin>, Vec<PendingPlugin>)> {
+    let dependencies = pending
+        .iter()
+        .map(|plugin| plugin.plugin.dependencies())
+        .collect::<Vec<_>>();
+
+    let mut deferred = vec![false; pending.len()];
+    loop {
+        let is_known = |id: TypeId| {
+            built.contains(&id)
+                || pending
+                    .iter()
+                    .zip(&deferred)
+                    .any(|(plugin, deferred)| plugin.id == id && !deferred)
+        };
+        let next = (0..pending.len()).find(|&index| {
+            !deferred[index]
+                && dependencies[index]
+                    .iter()
+                    .any(|dependency| !is_known(dependency.id))
+        });
+        match next {
+            Some(index) => deferred[index] = true,
+            None => break,
+        }
+    }
+
+    if !pending.is_empty() && deferred.iter().all(|&deferred| deferred) {
+        let missing = pending
+            .iter()
+            .zip(&dependencies)
+            .find_map(|(plugin, dependencies)| {
+                dependencies
+                    .iter()
+                    .find(|dependency| {
+                        !built.contains(&dependency.id)
+                            && pending.iter().all(|other| other.id != dependency.id)
+                    })
+                    .map(|dependency| (plugin, dependency))
+            });
+        let (plugin, dependency) = missing.unwrap();
+        return Err(Error::Plugin {
+            plugin: plugin.name.to_string(),
+            message: format!("depends on `{}`, which was not added", dependency.name),
+        });
+    }
+
+    let mut later = Vec::new();
+    let mut now = Vec::new();
+    let mut now_dependencies = Vec::new();
+    for ((plugin, plugin_dependencies), deferred) in
+        pending.into_iter().zip(dependencies).zip(deferred)
+    {
+        if deferred {
+            later.push(plugin);
+        } else {
+            now.push(Some(plugin));
+            now_dependencies.push(plugin_dependencies);
+        }
+    }
+    let (mut pending, dependencies) = (now, now_dependencies);
+
+    let mut ordered = Vec::<PendingPlugin>::with_capacity(pending.len());
+    while ordered.len() < pending.len() {
+        let is_ready = |index: usize| {
+            dependencies[index].iter().all(|dependency| {
+                built.contains(&dependency.id)
+                    || ordered.iter().any(|plugin| plugin.id == dependency.id)
+            })
+        };
+        let next = (0..pending.len()).find(|&index| pending[index].is_some() && is_ready(index));
+        match next {
+            Some(index) => ordered.push(pending[index].take().unwrap()),
+            None => {
+                let plugin = pending.iter().flatten().next().unwrap();
+                return Err(Error::Plugin {
+                    plugin: plugin.name.to_string(),
+                    message: "is part of a dependency cycle".to_string(),
+                });
+            }
+        }
+    }
+    Ok((ordered, later))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Audio;
+    struct Input;
+    struct Ui;
+    struct Ping;
+    struct Pong;
+
+    impl Plugin for Audio {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            builder
+        }
+    }
+
+    impl Plugin for Input {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Audio>()]
+        }
+    }
+
+    impl Plugin for Ui {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Input>()]
+        }
+    }
+
+    impl Plugin for Ping {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Pong>()]
+        }
+    }
+
+    impl Plugin for Pong {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Ping>()]
+        }
+    }
+
+    fn names(plugins: &[PendingPlugin]) -> Vec<&'static str> {
+        plugins.iter().map(PendingPlugin::name).collect()
+    }
+
+    #[test]
+    fn builds_dependencies_first() {
+        let pending = vec![
+            PendingPlugin::new(Ui),
+            PendingPlugin::new(Input),
+            PendingPlugin::new(Audio),
+        ];
+        let (now, later) = order(pending, &[]).unwrap();
+        assert_eq!(
+            names(&now),
+            vec![
+                type_name::<Audio>(),
+                type_name::<Input>(),
+                type_name::<Ui>()
+            ]
+        );
+        assert!(later.is_empty());
+
+        let pending = vec![PendingPlugin::new(Ui)];
+        let (now, _) = order(pending, &[TypeId::of::<Input>()]).unwrap();
+        assert_eq!(names(&now), vec![type_name::<Ui>()]);
+    }
+
+    #[test]
+    fn defers_plugins_with_dependencies_not_added_yet() {
+        let pending = vec![PendingPlugin::new(Ui), PendingPlugin::new(Audio)];
+        let (now, later) = order(pending, &[]).unwrap();
+        assert_eq!(names(&now), vec![type_name::<Audio>()]);
+        assert_eq!(names(&later), vec![type_name::<Ui>()]);
+    }
+
+    #[test]
+    fn rejects_missing_and_cyclic_dependencies() {
+        let missing = vec![PendingPlugin::new(Ui), PendingPlugin::new(Input)];
+        let cycle = vec![PendingPlugin::new(Ping), PendingPlugin::new(Pong)];
+
+        let cases = [
+            (missing, type_name::<Input>()),
+            (cycle, type_name::<Ping>()),
+        ];
+        for (pending, culprit) in cases {
+            match order(pending, &[]) {
+                Err(Error::Plugin { plugin, .. }) => assert_eq!(plugin, culprit),
+                Err(error) => panic!("unexpected error {}", error),
+                Ok(_) => panic!("ordered plugins with unmet dependencies"),
+            }
+        }
+    }
+}
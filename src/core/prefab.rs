@@ -0,0 +1,423 @@
+//! Entities described in files under the assets directory.
+//!
+//! A prefab lists entities like a [saved world](../serialization/index.html), and can include
+//! other prefabs. Included prefabs are found relative to the assets directory, and the
+//! components of their entities can be overridden per instance, by entity id:
+//!
+//! ```yaml
+//! entities:
+//!   - id: 0
+//!     components:
+//!       position: {x: 0.0, y: 0.0}
+//!   - prefab: enemies/goblin.yml
+//!     overrides:
+//!       0:
+//!         position: {x: 3.0}
+//! ```
+//!
+//! Overrides are merged into the included prefab's components field by field, and add the
+//! components the entity does not have.
+
+use crate::core::application::settings::merge;
+use crate::core::error::{Error, Result};
+use crate::ecs::entity::Entity;
+use crate::ecs::world::World;
+use crate::registry::ComponentRegistry;
+use crate::serialization;
+use crate::vfs::Vfs;
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Component values replacing those of a prefab's entities, by entity id and component name.
+pub type PrefabOverrides = BTreeMap<u64, BTreeMap<String, Value>>;
+
+#[derive(Deserialize)]
+struct PrefabFile {
+    entities: Vec<PrefabFileEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrefabFileEntity {
+    id: Option<u64>,
+    #[serde(default)]
+    components: BTreeMap<String, Value>,
+    prefab: Option<String>,
+    #[serde(default)]
+    overrides: PrefabOverrides,
+}
+
+#[derive(Clone, Debug)]
+enum PrefabEntity {
+    Entity {
+        id: Option<u64>,
+        components: BTreeMap<String, Value>,
+    },
+    Instance {
+        prefab: Arc<Prefab>,
+        overrides: PrefabOverrides,
+    },
+}
+
+/// A prefab loaded along with the prefabs it includes.
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    path: PathBuf,
+    entities: Vec<PrefabEntity>,
+}
+
+impl Prefab {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Creates the prefab's entities in `world`.
+    ///
+    /// Returns the created entities in the order they appear in the file, the entities of
+    /// included prefabs taking the place of their include. On error, nothing is added to `world`.
+    pub fn instantiate(
+        &self,
+        registry: &ComponentRegistry,
+        world: &mut World,
+    ) -> Result<Vec<Entity>> {
+        self.instantiate_with(registry, world, &PrefabOverrides::new())
+    }
+
+    /// Creates the prefab's entities in `world`, with the components in `overrides` merged
+    /// into those of the prefab.
+    pub fn instantiate_with(
+        &self,
+        registry: &ComponentRegistry,
+        world: &mut World,
+        overrides: &PrefabOverrides,
+    ) -> Result<Vec<Entity>> {
+        if let Some(id) = overrides.keys().find(|&&id| !self.has_entity(id)) {
+            return Err(self.parse_error(format!("no entity with id {} to override", id)));
+        }
+
+        // Included prefabs are instantiated first, and this prefab's own entities all at once
+        // afterwards, so that references between them can be resolved.
+        let mut included = Vec::new();
+        let mut own = Vec::new();
+        for entity in &self.entities {
+            match entity {
+                PrefabEntity::Entity { id, components } => {
+                    let mut components = components.clone();
+                    for (name, value) in id.and_then(|id| overrides.get(&id)).into_iter().flatten()
+                    {
+                        match components.get_mut(name) {
+                            Some(existing) => merge(existing, value.clone()),
+                            None => {
+                                components.insert(name.clone(), value.clone());
+                            }
+                        }
+                    }
+                    own.push((*id, components));
+                }
+                PrefabEntity::Instance { prefab, overrides } => {
+                    match prefab.instantiate_with(registry, world, overrides) {
+                        Ok(entities) => included.push(entities),
+                        Err(error) => {
+                            included
+                                .iter()
+                                .for_each(|entities| serialization::delete_all(world, entities));
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+        }
+
+        let own = match serialization::spawn(registry, world, own) {
+            Ok(own) => own,
+            Err(message) => {
+                included
+                    .iter()
+                    .for_each(|entities| serialization::delete_all(world, entities));
+                return Err(self.parse_error(message));
+            }
+        };
+
+        let mut own = own.into_iter();
+        let mut included = included.into_iter();
+        let entities = self
+            .entities
+            .iter()
+            .flat_map(|entity| match entity {
+                PrefabEntity::Entity { .. } => own.next().into_iter().collect::<Vec<_>>(),
+                PrefabEntity::Instance { .. } => included.next().unwrap_or_default(),
+            })
+            .collect();
+
+        Ok(entities)
+    }
+
+    fn has_entity(&self, id: u64) -> bool {
+        self.entities.iter().any(|entity| match entity {
+            PrefabEntity::Entity {
+                id: Some(entity_id),
+                ..
+            } => *entity_id == id,
+            _ => false,
+        })
+    }
+
+    fn parse_error(&self, message: String) -> Error {
+        Error::Parse {
+            path: self.path.clone(),
+            line: None,
+            column: None,
+            message,
+        }
+    }
+}
+
+/// Loads prefabs from the assets directory.
+///
+/// The application inserts one reading from its `Vfs` into `world.resources`.
+#[derive(Clone, Debug)]
+pub struct Prefabs {
+    vfs: Vfs,
+}
+
+impl Prefabs {
+    /// Creates a loader reading the files under `root`.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self::with_vfs(Vfs::new(root))
+    }
+
+    /// Creates a loader reading the files of `vfs`.
+    pub fn with_vfs(vfs: Vfs) -> Self {
+        Self { vfs }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.vfs.root()
+    }
+
+    /// Loads the prefab at `path`, relative to the assets directory, and the prefabs it includes.
+    pub fn load<P>(&self, path: P) -> Result<Prefab>
+    where
+        P: AsRef<Path>,
+    {
+        let mut loaded = HashMap::new();
+        let prefab = self.load_included(path.as_ref(), &mut Vec::new(), &mut loaded)?;
+        Ok(Prefab::clone(&prefab))
+    }
+
+    fn load_included(
+        &self,
+        path: &Path,
+        including: &mut Vec<PathBuf>,
+        loaded: &mut HashMap<PathBuf, Arc<Prefab>>,
+    ) -> Result<Arc<Prefab>> {
+        let relative_path = path;
+        let path = self.vfs.root().join(path);
+        if let Some(prefab) = loaded.get(&path) {
+            return Ok(prefab.clone());
+        }
+
+        let parse_error = |message: String| Error::Parse {
+            path: path.clone(),
+            line: None,
+            column: None,
+            message,
+        };
+
+        if including.contains(&path) {
+            return Err(parse_error("prefab includes itself".to_string()));
+        }
+
+        let format = serialization::format_of(&path)?;
+        let contents = self.vfs.read_to_string(relative_path)?;
+        let file = format.parse::<PrefabFile>(&path, &contents)?;
+
+        including.push(path.clone());
+        let mut entities = Vec::with_capacity(file.entities.len());
+        for entity in file.entities {
+            entities.push(match entity {
+                PrefabFileEntity {
+                    id,
+                    components,
+                    prefab: None,
+                    overrides,
+                } => {
+                    if !overrides.is_empty() {
+                        return Err(parse_error(
+                            "`overrides` can only be given along with `prefab`".to_string(),
+                        ));
+                    }
+                    PrefabEntity::Entity { id, components }
+                }
+                PrefabFileEntity {
+                    id: None,
+                    components,
+                    prefab: Some(prefab),
+                    overrides,
+                } if components.is_empty() => PrefabEntity::Instance {
+                    prefab: self.load_included(Path::new(&prefab), including, loaded)?,
+                    overrides,
+                },
+                PrefabFileEntity {
+                    prefab: Some(prefab),
+                    ..
+                } => {
+                    return Err(parse_error(format!(
+                        "the include of `{}` can only have `overrides`",
+                        prefab
+                    )));
+                }
+            });
+        }
+        including.pop();
+
+        let prefab = Arc::new(Prefab {
+            path: path.clone(),
+            entities,
+        });
+        loaded.insert(path, prefab.clone());
+        Ok(prefab)
+    }
+}
+
+impl Default for Prefabs {
+    fn default() -> Self {
+        Self::new("./assets")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::{IntoQuery, Read};
+    use crate::ecs::world::Universe;
+    use serde::Serialize;
+    use std::fs;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Follow(#[serde(with = "serialization::entity")] Entity);
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry
+            .register_serializable::<Position>("position")
+            .unwrap();
+        registry.register_serializable::<Health>("health").unwrap();
+        registry.register_serializable::<Follow>("follow").unwrap();
+        registry
+    }
+
+    fn assets(name: &str, files: &[(&str, &str)]) -> Prefabs {
+        let root =
+            std::env::temp_dir().join(format!("crius-prefabs-{}-{}", name, std::process::id()));
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        Prefabs::new(root)
+    }
+
+    #[test]
+    fn instantiates_included_prefabs_with_overrides() {
+        let prefabs = assets(
+            "includes",
+            &[
+                (
+                    "enemies/goblin.yml",
+                    "entities:\n  - id: 0\n    components:\n      position: {x: 1.0, y: 1.0}\n      health: 10\n  - components:\n      follow: 0\n",
+                ),
+                (
+                    "level.yml",
+                    "entities:\n  - id: 7\n    components:\n      health: 100\n  - prefab: enemies/goblin.yml\n    overrides:\n      0:\n        position: {x: 3.0}\n",
+                ),
+            ],
+        );
+
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let entities = prefabs
+            .load("level.yml")
+            .unwrap()
+            .instantiate(&registry(), &mut world)
+            .unwrap();
+        fs::remove_dir_all(prefabs.root()).unwrap();
+
+        assert_eq!(entities.len(), 3);
+        assert_eq!(
+            *world.get_component::<Health>(entities[0]).unwrap(),
+            Health(100)
+        );
+        assert_eq!(
+            *world.get_component::<Position>(entities[1]).unwrap(),
+            Position { x: 3.0, y: 1.0 }
+        );
+        assert_eq!(
+            *world.get_component::<Health>(entities[1]).unwrap(),
+            Health(10)
+        );
+        assert_eq!(
+            *world.get_component::<Follow>(entities[2]).unwrap(),
+            Follow(entities[1])
+        );
+    }
+
+    #[test]
+    fn rejects_prefabs_including_themselves() {
+        let prefabs = assets(
+            "cycle",
+            &[
+                ("a.yml", "entities:\n  - prefab: b.yml\n"),
+                ("b.yml", "entities:\n  - prefab: a.yml\n"),
+            ],
+        );
+
+        let error = prefabs.load("a.yml").unwrap_err();
+        fs::remove_dir_all(prefabs.root()).unwrap();
+
+        match error {
+            Error::Parse { path, .. } => assert!(path.ends_with("a.yml")),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn leaves_the_world_untouched_on_bad_overrides() {
+        let prefabs = assets(
+            "overrides",
+            &[
+                ("goblin.yml", "entities:\n  - id: 0\n    components:\n      health: 10\n"),
+                (
+                    "level.yml",
+                    "entities:\n  - components:\n      health: 1\n  - prefab: goblin.yml\n  - prefab: goblin.yml\n    overrides:\n      0:\n        health: lots\n",
+                ),
+            ],
+        );
+
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let result = prefabs
+            .load("level.yml")
+            .unwrap()
+            .instantiate(&registry(), &mut world);
+        fs::remove_dir_all(prefabs.root()).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(<Read<Health>>::query().iter_immutable(&world).count(), 0);
+    }
+}
@@ -0,0 +1,329 @@
+//! Type-erased access to registered component types.
+//!
+//! legion can only move whole worlds around with `World::merge`. The registry remembers how
+//! to copy each registered component type, so individual entities can be cloned or moved
+//! from one world to another. Types registered by name can also be saved to and loaded
+//! from files, see the [`serialization`](../serialization/index.html) module.
+
+use crate::core::error::{Error, Result};
+use crate::ecs::entity::Entity;
+use crate::ecs::query::{IntoQuery, Read};
+use crate::ecs::storage::Component;
+use crate::ecs::world::World;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Maps entities of a source world to the entities created for them in a destination world.
+pub type EntityMap = HashMap<Entity, Entity>;
+
+pub(crate) type BoxedComponent = Box<dyn Any + Send>;
+
+#[derive(Clone)]
+struct ComponentRegistration {
+    clone: fn(&World, Entity) -> Option<BoxedComponent>,
+    spawn: fn(&mut World, BoxedComponent) -> Entity,
+    add: fn(&mut World, Entity, BoxedComponent),
+}
+
+#[derive(Clone)]
+pub(crate) struct SerializableRegistration {
+    type_id: TypeId,
+    pub(crate) entities: fn(&World) -> Vec<Entity>,
+    pub(crate) serialize: fn(&World, Entity) -> Option<serde_yaml::Result<Value>>,
+    pub(crate) deserialize: fn(Value) -> serde_yaml::Result<BoxedComponent>,
+    pub(crate) add: fn(&mut World, Entity, BoxedComponent),
+}
+
+#[derive(Clone, Default)]
+struct Registrations {
+    transferable: HashMap<TypeId, ComponentRegistration>,
+    // Ordered by name, so that saved files list components in a stable order.
+    serializable: BTreeMap<String, SerializableRegistration>,
+}
+
+/// The component types entities can be transferred and saved with.
+///
+/// The application inserts its registry into `world.resources`. Cloning a registry is cheap.
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    registrations: Arc<Registrations>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T`. Registering the same type more than once has no effect.
+    pub fn register<T>(&mut self)
+    where
+        T: Component + Clone,
+    {
+        Arc::make_mut(&mut self.registrations)
+            .transferable
+            .entry(TypeId::of::<T>())
+            .or_insert(ComponentRegistration {
+                clone: clone_component::<T>,
+                spawn: spawn_component::<T>,
+                add: add_component::<T>,
+            });
+    }
+
+    pub fn is_registered<T>(&self) -> bool
+    where
+        T: Component,
+    {
+        self.registrations
+            .transferable
+            .contains_key(&TypeId::of::<T>())
+    }
+
+    /// Registers `T` under `name`, so that it can be saved to and loaded from files.
+    /// Registering the same type under the same name more than once has no effect.
+    ///
+    /// Fails if `name` is already taken by another type, leaving the registry unchanged.
+    pub fn register_serializable<T>(&mut self, name: &str) -> Result<()>
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        let registration = Arc::make_mut(&mut self.registrations)
+            .serializable
+            .entry(name.to_string())
+            .or_insert(SerializableRegistration {
+                type_id: TypeId::of::<T>(),
+                entities: component_entities::<T>,
+                serialize: serialize_component::<T>,
+                deserialize: deserialize_component::<T>,
+                add: add_component::<T>,
+            });
+
+        if registration.type_id != TypeId::of::<T>() {
+            return Err(Error::Component {
+                component: name.to_string(),
+                message: "is already registered for another type".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_serializable<T>(&self) -> bool
+    where
+        T: Component,
+    {
+        self.registrations
+            .serializable
+            .values()
+            .any(|registration| registration.type_id == TypeId::of::<T>())
+    }
+
+    /// The entities of `world` that have at least one component of a serializable type.
+    pub fn serializable_entities(&self, world: &World) -> Vec<Entity> {
+        let mut seen = HashSet::new();
+
+        self.registrations
+            .serializable
+            .values()
+            .flat_map(|registration| (registration.entities)(world))
+            .filter(|entity| seen.insert(*entity))
+            .collect()
+    }
+
+    pub(crate) fn serializable(
+        &self,
+    ) -> impl Iterator<Item = (&str, &SerializableRegistration)> + '_ {
+        self.registrations
+            .serializable
+            .iter()
+            .map(|(name, registration)| (name.as_str(), registration))
+    }
+
+    pub(crate) fn serializable_named(&self, name: &str) -> Option<&SerializableRegistration> {
+        self.registrations.serializable.get(name)
+    }
+
+    /// Creates copies of `entities` from `source` in `destination`.
+    ///
+    /// Only components of registered types are copied. Entities that are not alive or
+    /// have no registered components are left out of the returned map.
+    pub fn clone_entities(
+        &self,
+        source: &World,
+        destination: &mut World,
+        entities: &[Entity],
+    ) -> EntityMap {
+        let mut entity_map = EntityMap::with_capacity(entities.len());
+
+        for &entity in entities {
+            if !source.is_alive(entity) || entity_map.contains_key(&entity) {
+                continue;
+            }
+
+            let mut components =
+                self.registrations
+                    .transferable
+                    .values()
+                    .filter_map(|registration| {
+                        (registration.clone)(source, entity)
+                            .map(|component| (registration, component))
+                    });
+
+            if let Some((registration, component)) = components.next() {
+                let cloned = (registration.spawn)(destination, component);
+                for (registration, component) in components {
+                    (registration.add)(destination, cloned, component);
+                }
+                entity_map.insert(entity, cloned);
+            }
+        }
+
+        entity_map
+    }
+
+    /// Moves `entities` from `source` to `destination`.
+    ///
+    /// Entities are cloned as in [`clone_entities`](#method.clone_entities) and then deleted
+    /// from `source`, so they get new ids. legion only keeps ids when a whole world is merged
+    /// into another one of the same universe, see `World::merge`. Entities missing from the
+    /// returned map are left untouched.
+    pub fn move_entities(
+        &self,
+        source: &mut World,
+        destination: &mut World,
+        entities: &[Entity],
+    ) -> EntityMap {
+        let entity_map = self.clone_entities(source, destination, entities);
+
+        for entity in entity_map.keys() {
+            source.delete(*entity);
+        }
+
+        entity_map
+    }
+}
+
+fn clone_component<T>(world: &World, entity: Entity) -> Option<BoxedComponent>
+where
+    T: Component + Clone,
+{
+    world
+        .get_component::<T>(entity)
+        .map(|component| Box::new(T::clone(&component)) as BoxedComponent)
+}
+
+fn component_entities<T>(world: &World) -> Vec<Entity>
+where
+    T: Component,
+{
+    <Read<T>>::query()
+        .iter_entities_immutable(world)
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+fn serialize_component<T>(world: &World, entity: Entity) -> Option<serde_yaml::Result<Value>>
+where
+    T: Component + Serialize,
+{
+    world
+        .get_component::<T>(entity)
+        .map(|component| serde_yaml::to_value(&*component))
+}
+
+fn deserialize_component<T>(value: Value) -> serde_yaml::Result<BoxedComponent>
+where
+    T: Component + DeserializeOwned,
+{
+    serde_yaml::from_value::<T>(value).map(|component| Box::new(component) as BoxedComponent)
+}
+
+fn spawn_component<T>(world: &mut World, component: BoxedComponent) -> Entity
+where
+    T: Component,
+{
+    let component = *component
+        .downcast::<T>()
+        .expect("Component registered under the wrong type.");
+    world.insert((), vec![(component,)])[0]
+}
+
+fn add_component<T>(world: &mut World, entity: Entity, component: BoxedComponent)
+where
+    T: Component,
+{
+    let component = *component
+        .downcast::<T>()
+        .expect("Component registered under the wrong type.");
+    world.add_component(entity, component)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::world::Universe;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Unregistered;
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<Position>();
+        registry.register::<Health>();
+        registry
+    }
+
+    #[test]
+    fn clones_registered_components() {
+        let universe = Universe::new();
+        let mut source = universe.create_world();
+        let mut destination = universe.create_world();
+
+        let entity = source.insert((), vec![(Position(1.0), Health(3), Unregistered)])[0];
+
+        let entity_map = registry().clone_entities(&source, &mut destination, &[entity]);
+        let cloned = entity_map[&entity];
+
+        assert_ne!(cloned, entity);
+        assert!(source.is_alive(entity));
+        assert_eq!(
+            *destination.get_component::<Position>(cloned).unwrap(),
+            Position(1.0)
+        );
+        assert_eq!(
+            *destination.get_component::<Health>(cloned).unwrap(),
+            Health(3)
+        );
+        assert!(destination.get_component::<Unregistered>(cloned).is_none());
+    }
+
+    #[test]
+    fn moves_entities_and_skips_unknown_ones() {
+        let universe = Universe::new();
+        let mut source = universe.create_world();
+        let mut destination = universe.create_world();
+
+        let moved = source.insert((), vec![(Position(2.0),)])[0];
+        let unknown = source.insert((), vec![(Unregistered,)])[0];
+
+        let entity_map = registry().move_entities(&mut source, &mut destination, &[moved, unknown]);
+
+        assert_eq!(entity_map.len(), 1);
+        assert!(!source.is_alive(moved));
+        assert!(source.is_alive(unknown));
+        assert_eq!(
+            *destination
+                .get_component::<Position>(entity_map[&moved])
+                .unwrap(),
+            Position(2.0)
+        );
+    }
+}
@@ -0,0 +1,674 @@
+//! Recording the events delivered to the scenes and replaying them without a window.
+//!
+//! An application built with `ApplicationBuilder::record` writes every event handed to
+//! the scenes, along with the duration of every frame, to a replay file. While the
+//! application runs, frames are appended to a journal next to the file as they complete,
+//! one JSON line each, flushed every few frames. The file itself is written once, when the
+//! application stops or panics, and the journal is then removed. Loading a recording whose
+//! application was killed reads the journal it left instead, so a crash keeps the frames
+//! leading up to it. An application built with `ApplicationBuilder::replay` runs headless
+//! and feeds the recorded events and frame durations back, so that the scenes and systems
+//! go through the same frames again.
+//!
+//! Every recorded frame can carry a [`checksum`](../serialization/fn.checksum.html) of the
+//! serializable components named with `ApplicationBuilder::with_checksum_component`. A replay
+//! compares them against its own world and stops with `Error::Diverged` at the first frame
+//! that differs.
+//!
+//! Events that cannot be reproduced, such as theme changes and user events, are left out of
+//! recordings. User events hold values of any type, which cannot be written to a file, so
+//! replays are only deterministic for applications whose scenes do not change the world in
+//! response to them: a replay of an application that does can diverge from the recording
+//! at the first frame a user event arrived in.
+//!
+//! Window events keep the name of their window instead of its id. Replays run without
+//! opening windows, but register the windows of the settings and those the events name in
+//! the `Windows` resource, so the events carry the ids the windows had when recorded.
+
+use crate::core::error::{Error, Result};
+use crate::core::event::{ApplicationEvent, DeviceEvent, Event, GamepadEvent};
+use crate::core::event::{KeyboardInput, ModifiersState, MouseButton};
+use crate::core::event::{VirtualKeyCode, WindowEvent};
+use crate::core::serialization::format_of;
+use crate::core::window::Windows;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceId, ElementState, MouseScrollDelta, Touch, TouchPhase};
+
+/// The recordable part of a `WindowEvent`. Device ids are not kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedWindowEvent {
+    Resized(PhysicalSize<u32>),
+    Moved(PhysicalPosition<i32>),
+    CloseRequested,
+    Destroyed,
+    DroppedFile(PathBuf),
+    HoveredFile(PathBuf),
+    HoveredFileCancelled,
+    ReceivedCharacter(char),
+    Focused(bool),
+    KeyboardInput {
+        scancode: u32,
+        state: ElementState,
+        virtual_keycode: Option<VirtualKeyCode>,
+        is_synthetic: bool,
+    },
+    ModifiersChanged(ModifiersState),
+    CursorMoved(PhysicalPosition<f64>),
+    CursorEntered,
+    CursorLeft,
+    MouseWheel {
+        delta: MouseScrollDelta,
+        phase: TouchPhase,
+    },
+    MouseInput {
+        state: ElementState,
+        button: MouseButton,
+    },
+    TouchpadPressure {
+        pressure: f32,
+        stage: i64,
+    },
+    AxisMotion {
+        axis: u32,
+        value: f64,
+    },
+    Touch {
+        phase: TouchPhase,
+        location: PhysicalPosition<f64>,
+        id: u64,
+    },
+    ScaleFactorChanged {
+        scale_factor: f64,
+        new_inner_size: PhysicalSize<u32>,
+    },
+}
+
+/// The recordable part of a `DeviceEvent`. Device ids are not kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedDeviceEvent {
+    Added,
+    Removed,
+    MouseMotion { delta: (f64, f64) },
+    MouseWheel { delta: MouseScrollDelta },
+    Motion { axis: u32, value: f64 },
+    Button { button: u32, state: ElementState },
+    Key(KeyboardInput),
+    Text { codepoint: char },
+}
+
+impl From<&DeviceEvent> for RecordedDeviceEvent {
+    fn from(event: &DeviceEvent) -> Self {
+        match *event {
+            DeviceEvent::Added => RecordedDeviceEvent::Added,
+            DeviceEvent::Removed => RecordedDeviceEvent::Removed,
+            DeviceEvent::MouseMotion { delta } => RecordedDeviceEvent::MouseMotion { delta },
+            DeviceEvent::MouseWheel { delta } => RecordedDeviceEvent::MouseWheel { delta },
+            DeviceEvent::Motion { axis, value } => RecordedDeviceEvent::Motion { axis, value },
+            DeviceEvent::Button { button, state } => RecordedDeviceEvent::Button { button, state },
+            DeviceEvent::Key(input) => RecordedDeviceEvent::Key(input),
+            DeviceEvent::Text { codepoint } => RecordedDeviceEvent::Text { codepoint },
+        }
+    }
+}
+
+impl From<&RecordedDeviceEvent> for DeviceEvent {
+    fn from(event: &RecordedDeviceEvent) -> Self {
+        match *event {
+            RecordedDeviceEvent::Added => DeviceEvent::Added,
+            RecordedDeviceEvent::Removed => DeviceEvent::Removed,
+            RecordedDeviceEvent::MouseMotion { delta } => DeviceEvent::MouseMotion { delta },
+            RecordedDeviceEvent::MouseWheel { delta } => DeviceEvent::MouseWheel { delta },
+            RecordedDeviceEvent::Motion { axis, value } => DeviceEvent::Motion { axis, value },
+            RecordedDeviceEvent::Button { button, state } => DeviceEvent::Button { button, state },
+            RecordedDeviceEvent::Key(input) => DeviceEvent::Key(input),
+            RecordedDeviceEvent::Text { codepoint } => DeviceEvent::Text { codepoint },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Application(ApplicationEvent),
+    Window {
+        window: String,
+        event: RecordedWindowEvent,
+    },
+    Device(RecordedDeviceEvent),
+    Gamepad(GamepadEvent),
+}
+
+impl RecordedEvent {
+    /// The recordable part of `event`, or `None` if it cannot be reproduced. Window events
+    /// are only recorded for the windows in `windows`.
+    pub fn from_event(event: &Event, windows: Option<&Windows>) -> Option<Self> {
+        let (id, event) = match event {
+            Event::Application(event) => return Some(RecordedEvent::Application(*event)),
+            Event::Device(event) => return Some(RecordedEvent::Device(event.into())),
+            Event::Gamepad(event) => return Some(RecordedEvent::Gamepad(event.clone())),
+            // Not reproducible, see the module documentation.
+            Event::User(_) => return None,
+            Event::Window(id, event) => (id, event),
+        };
+        let window = windows?.name_of(*id)?.to_string();
+
+        #[allow(deprecated)]
+        let event = match event {
+            WindowEvent::Resized(size) => RecordedWindowEvent::Resized(*size),
+            WindowEvent::Moved(position) => RecordedWindowEvent::Moved(*position),
+            WindowEvent::CloseRequested => RecordedWindowEvent::CloseRequested,
+            WindowEvent::Destroyed => RecordedWindowEvent::Destroyed,
+            WindowEvent::DroppedFile(path) => RecordedWindowEvent::DroppedFile(path.clone()),
+            WindowEvent::HoveredFile(path) => RecordedWindowEvent::HoveredFile(path.clone()),
+            WindowEvent::HoveredFileCancelled => RecordedWindowEvent::HoveredFileCancelled,
+            WindowEvent::ReceivedCharacter(character) => {
+                RecordedWindowEvent::ReceivedCharacter(*character)
+            }
+            WindowEvent::Focused(focused) => RecordedWindowEvent::Focused(*focused),
+            WindowEvent::KeyboardInput {
+                input,
+                is_synthetic,
+                ..
+            } => RecordedWindowEvent::KeyboardInput {
+                scancode: input.scancode,
+                state: input.state,
+                virtual_keycode: input.virtual_keycode,
+                is_synthetic: *is_synthetic,
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                RecordedWindowEvent::ModifiersChanged(*modifiers)
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                RecordedWindowEvent::CursorMoved(*position)
+            }
+            WindowEvent::CursorEntered { .. } => RecordedWindowEvent::CursorEntered,
+            WindowEvent::CursorLeft { .. } => RecordedWindowEvent::CursorLeft,
+            WindowEvent::MouseWheel { delta, phase, .. } => RecordedWindowEvent::MouseWheel {
+                delta: *delta,
+                phase: *phase,
+            },
+            WindowEvent::MouseInput { state, button, .. } => RecordedWindowEvent::MouseInput {
+                state: *state,
+                button: *button,
+            },
+            WindowEvent::TouchpadPressure {
+                pressure, stage, ..
+            } => RecordedWindowEvent::TouchpadPressure {
+                pressure: *pressure,
+                stage: *stage,
+            },
+            WindowEvent::AxisMotion { axis, value, .. } => RecordedWindowEvent::AxisMotion {
+                axis: *axis,
+                value: *value,
+            },
+            WindowEvent::Touch(touch) => RecordedWindowEvent::Touch {
+                phase: touch.phase,
+                location: touch.location,
+                id: touch.id,
+            },
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => RecordedWindowEvent::ScaleFactorChanged {
+                scale_factor: *scale_factor,
+                new_inner_size: **new_inner_size,
+            },
+            WindowEvent::ThemeChanged(_) => return None,
+        };
+
+        Some(RecordedEvent::Window { window, event })
+    }
+
+    /// Recreates the recorded event. Window events get the id of the window with the
+    /// recorded name, which is added to `windows` if it is not there yet.
+    /// `new_inner_size` holds the size of a recorded `ScaleFactorChanged` event while the
+    /// event is handled.
+    pub fn to_event<'a>(
+        &self,
+        windows: &mut Windows,
+        new_inner_size: &'a mut PhysicalSize<u32>,
+    ) -> Event<'a> {
+        let (window, event) = match self {
+            RecordedEvent::Application(event) => return Event::Application(*event),
+            RecordedEvent::Device(event) => return Event::Device(event.into()),
+            RecordedEvent::Gamepad(event) => return Event::Gamepad(event.clone()),
+            RecordedEvent::Window { window, event } => (window, event),
+        };
+
+        let window_id = windows
+            .id_of(window)
+            .unwrap_or_else(|| windows.insert_placeholder(window.clone()));
+        // The id is never handed to winit, which is the only thing `dummy` forbids.
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::empty();
+
+        #[allow(deprecated)]
+        let event = match event {
+            RecordedWindowEvent::Resized(size) => WindowEvent::Resized(*size),
+            RecordedWindowEvent::Moved(position) => WindowEvent::Moved(*position),
+            RecordedWindowEvent::CloseRequested => WindowEvent::CloseRequested,
+            RecordedWindowEvent::Destroyed => WindowEvent::Destroyed,
+            RecordedWindowEvent::DroppedFile(path) => WindowEvent::DroppedFile(path.clone()),
+            RecordedWindowEvent::HoveredFile(path) => WindowEvent::HoveredFile(path.clone()),
+            RecordedWindowEvent::HoveredFileCancelled => WindowEvent::HoveredFileCancelled,
+            RecordedWindowEvent::ReceivedCharacter(character) => {
+                WindowEvent::ReceivedCharacter(*character)
+            }
+            RecordedWindowEvent::Focused(focused) => WindowEvent::Focused(*focused),
+            RecordedWindowEvent::KeyboardInput {
+                scancode,
+                state,
+                virtual_keycode,
+                is_synthetic,
+            } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode: *scancode,
+                    state: *state,
+                    virtual_keycode: *virtual_keycode,
+                    modifiers,
+                },
+                is_synthetic: *is_synthetic,
+            },
+            RecordedWindowEvent::ModifiersChanged(modifiers) => {
+                WindowEvent::ModifiersChanged(*modifiers)
+            }
+            RecordedWindowEvent::CursorMoved(position) => WindowEvent::CursorMoved {
+                device_id,
+                position: *position,
+                modifiers,
+            },
+            RecordedWindowEvent::CursorEntered => WindowEvent::CursorEntered { device_id },
+            RecordedWindowEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+            RecordedWindowEvent::MouseWheel { delta, phase } => WindowEvent::MouseWheel {
+                device_id,
+                delta: *delta,
+                phase: *phase,
+                modifiers,
+            },
+            RecordedWindowEvent::MouseInput { state, button } => WindowEvent::MouseInput {
+                device_id,
+                state: *state,
+                button: *button,
+                modifiers,
+            },
+            RecordedWindowEvent::TouchpadPressure { pressure, stage } => {
+                WindowEvent::TouchpadPressure {
+                    device_id,
+                    pressure: *pressure,
+                    stage: *stage,
+                }
+            }
+            RecordedWindowEvent::AxisMotion { axis, value } => WindowEvent::AxisMotion {
+                device_id,
+                axis: *axis,
+                value: *value,
+            },
+            RecordedWindowEvent::Touch {
+                phase,
+                location,
+                id,
+            } => WindowEvent::Touch(Touch {
+                device_id,
+                phase: *phase,
+                location: *location,
+                force: None,
+                id: *id,
+            }),
+            RecordedWindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size: size,
+            } => {
+                *new_inner_size = *size;
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor: *scale_factor,
+                    new_inner_size,
+                }
+            }
+        };
+
+        Event::Window(window_id, event)
+    }
+}
+
+/// The events handed to the scenes before a frame, and how long the frame took.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub delta: Duration,
+    #[serde(default)]
+    pub events: Vec<RecordedEvent>,
+    /// The checksum of the world at the end of the frame.
+    #[serde(default)]
+    pub checksum: Option<u32>,
+}
+
+/// A recording of an application run. The file format is picked by extension.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    /// The names of the serializable components the checksums cover.
+    #[serde(default)]
+    pub components: Vec<String>,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// Loads the recording at `path`, or the journal a recording that did not finish left
+    /// next to it.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        // Journals are removed once the file is written, so one that is left is newer.
+        let journal = journal_path(path);
+        if journal.exists() {
+            return Self::load_journal(&journal);
+        }
+
+        let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        format_of(path)?.parse(path, &contents)
+    }
+
+    /// Reads a journal: a header with the components, then one frame per line. A last line
+    /// cut short by a crash is left out.
+    fn load_journal(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |line: usize, error: serde_json::Error| Error::Parse {
+            path: path.to_path_buf(),
+            line: Some(line + 1),
+            column: Some(error.column()),
+            message: error.to_string(),
+        };
+
+        let lines = contents.lines().collect::<Vec<_>>();
+        let mut replay = Replay::default();
+        for (index, line) in lines.iter().enumerate() {
+            let is_last = index + 1 == lines.len();
+            if index == 0 {
+                let header: JournalHeader =
+                    serde_json::from_str(line).map_err(|error| parse_error(index, error))?;
+                replay.components = header.components;
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(frame) => replay.frames.push(frame),
+                Err(error) if !(is_last && error.is_eof()) => {
+                    return Err(parse_error(index, error))
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = format_of(path)?.serialize(path, self)?;
+        fs::write(path, contents).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// The first line of a journal.
+#[derive(Serialize, Deserialize)]
+struct JournalHeader {
+    components: Vec<String>,
+}
+
+/// The journal of the recording at `path`.
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = path.to_path_buf().into_os_string();
+    journal.push(".journal");
+    PathBuf::from(journal)
+}
+
+/// The number of frames completed between two flushes of the journal.
+const FLUSH_INTERVAL: usize = 60;
+
+/// Collects the frames of a running application into a `Replay`, appended to its journal
+/// as frames complete and written to its file once recording ends.
+pub(crate) struct Recorder {
+    path: PathBuf,
+    replay: Replay,
+    events: Vec<RecordedEvent>,
+    delta: Option<Duration>,
+    /// Opened with the first frame.
+    journal: Option<BufWriter<File>>,
+    /// The frames appended to the journal since it was last flushed.
+    unflushed: usize,
+    saved: bool,
+}
+
+impl Recorder {
+    pub(crate) fn new(path: PathBuf, components: Vec<String>) -> Self {
+        Self {
+            path,
+            replay: Replay {
+                components,
+                frames: Vec::new(),
+            },
+            events: Vec::new(),
+            delta: None,
+            journal: None,
+            unflushed: 0,
+            saved: false,
+        }
+    }
+
+    pub(crate) fn components(&self) -> &[String] {
+        &self.replay.components
+    }
+
+    pub(crate) fn record(&mut self, event: &Event, windows: Option<&Windows>) {
+        self.events
+            .extend(RecordedEvent::from_event(event, windows));
+    }
+
+    /// Sets the duration of the frame being recorded.
+    pub(crate) fn set_delta(&mut self, delta: Duration) {
+        self.delta = Some(delta);
+    }
+
+    /// Completes the frame being recorded, unless it never updated, and appends it to the
+    /// journal, which is flushed every `FLUSH_INTERVAL` frames.
+    pub(crate) fn end_frame(&mut self, checksum: Option<u32>) -> Result<()> {
+        let delta = match self.delta.take() {
+            Some(delta) => delta,
+            None => return Ok(()),
+        };
+        let frame = ReplayFrame {
+            delta,
+            events: std::mem::take(&mut self.events),
+            checksum,
+        };
+        let appended = self.append(&frame);
+        self.replay.frames.push(frame);
+        appended
+    }
+
+    /// Writes the completed frames to the file and removes the journal. Events recorded
+    /// after the last frame are left out.
+    ///
+    /// The recording is written next to its file and then moved over it, so that the
+    /// journal stays usable if the application dies while writing.
+    pub(crate) fn save(&mut self) -> Result<()> {
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| Error::Io { path, source }
+        };
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let contents = format_of(&self.path)?.serialize(&self.path, &self.replay)?;
+        fs::write(&partial, contents)
+            .and_then(|()| fs::rename(&partial, &self.path))
+            .map_err(io_error(&self.path))?;
+        self.saved = true;
+
+        if self.journal.take().is_some() {
+            let journal = journal_path(&self.path);
+            fs::remove_file(&journal).map_err(io_error(&journal))?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, frame: &ReplayFrame) -> Result<()> {
+        let path = journal_path(&self.path);
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+
+        let journal = match &mut self.journal {
+            Some(journal) => journal,
+            None => {
+                let mut journal = BufWriter::new(File::create(&path).map_err(io_error)?);
+                let header = JournalHeader {
+                    components: self.replay.components.clone(),
+                };
+                write_line(&mut journal, &header).map_err(io_error)?;
+                self.journal.insert(journal)
+            }
+        };
+        write_line(journal, frame).map_err(io_error)?;
+
+        self.unflushed += 1;
+        if self.unflushed >= FLUSH_INTERVAL {
+            self.unflushed = 0;
+            journal.flush().map_err(io_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `value` as a line of JSON.
+fn write_line<T>(writer: &mut impl Write, value: &T) -> std::io::Result<()>
+where
+    T: Serialize,
+{
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Only happens when the application did not stop, usually because it panicked.
+        if !self.saved {
+            let _ = self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::input::{GamepadButton, GamepadEventKind, GamepadId};
+
+    #[test]
+    fn round_trips_events_through_a_file() {
+        let path = std::env::temp_dir().join(format!("crius-replay-{}.yml", std::process::id()));
+
+        let mut size = PhysicalSize::new(800, 600);
+        let mut windows = Windows::default();
+        let main = windows.insert_placeholder("main".to_string());
+        let inspector = windows.insert_placeholder("inspector".to_string());
+        let events = vec![
+            Event::Application(ApplicationEvent::Resumed),
+            Event::Window(inspector, WindowEvent::ReceivedCharacter('x')),
+            Event::Device(DeviceEvent::MouseMotion { delta: (1.0, -2.0) }),
+            Event::Window(
+                main,
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor: 2.0,
+                    new_inner_size: &mut size,
+                },
+            ),
+            Event::Gamepad(GamepadEvent {
+                id: GamepadId(0),
+                kind: GamepadEventKind::ButtonPressed(GamepadButton::South),
+            }),
+        ];
+
+        let mut recorder = Recorder::new(path.clone(), vec!["position".to_string()]);
+        for event in &events {
+            recorder.record(event, Some(&windows));
+        }
+        recorder.set_delta(Duration::from_millis(16));
+        recorder.end_frame(Some(7)).unwrap();
+        recorder.record(&Event::Application(ApplicationEvent::Terminating), None);
+        recorder.save().unwrap();
+
+        let replay = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        let replay = replay.unwrap();
+
+        assert_eq!(replay.components, vec!["position".to_string()]);
+        assert_eq!(replay.frames.len(), 1);
+        assert_eq!(replay.frames[0].checksum, Some(7));
+
+        // Replays register the windows of the settings before any event arrives.
+        let mut replayed_windows = Windows::default();
+        replayed_windows.insert_placeholder("main".to_string());
+        let mut scratch = PhysicalSize::new(0, 0);
+        let replayed = replay.frames[0]
+            .events
+            .iter()
+            .map(|event| format!("{:?}", event.to_event(&mut replayed_windows, &mut scratch)))
+            .collect::<Vec<_>>();
+        let expected = events
+            .iter()
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>();
+        assert_eq!(replayed, expected);
+        assert_eq!(replayed_windows.id_of("inspector"), Some(inspector));
+    }
+
+    #[test]
+    fn writes_frames_while_recording_and_when_dropped() {
+        let path =
+            std::env::temp_dir().join(format!("crius-replay-partial-{}.yml", std::process::id()));
+
+        let components = vec!["position".to_string()];
+        let mut recorder = Recorder::new(path.clone(), components.clone());
+        for _ in 0..FLUSH_INTERVAL + 1 {
+            recorder.set_delta(Duration::from_millis(16));
+            recorder.end_frame(None).unwrap();
+        }
+        // As if killed: only the flushed frames reach the journal.
+        std::mem::forget(recorder);
+        let killed = Replay::load(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(killed.components, components);
+        assert_eq!(killed.frames.len(), FLUSH_INTERVAL);
+
+        let mut recorder = Recorder::new(path.clone(), Vec::new());
+        recorder.set_delta(Duration::from_millis(16));
+        recorder.end_frame(Some(3)).unwrap();
+        drop(recorder);
+        assert!(!journal_path(&path).exists());
+        let saved = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        let saved = saved.unwrap();
+        assert_eq!(saved.frames.len(), 1);
+        assert_eq!(saved.frames[0].checksum, Some(3));
+    }
+}
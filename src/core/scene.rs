@@ -1,5 +1,18 @@
+use crate::application::settings::{FramePacingSettings, WindowSettings};
+use crate::ecs::entity::Entity;
 use crate::ecs::world::{Universe, World};
-use crate::event::Event;
+use crate::error::Result;
+use crate::event::{Event, WindowId};
+use crate::pacing::FramePacing;
+use crate::prefab::Prefabs;
+use crate::registry::{ComponentRegistry, EntityMap};
+use crate::schedule::{ScheduleBuilder, Stage, StageSchedules};
+use crate::serialization;
+use crate::time::Time;
+use crate::timestep::FixedTimestep;
+use crate::window::{WindowCommand, WindowCommands, WindowControl, Windows};
+use std::mem;
+use std::path::Path;
 
 pub enum Transition {
     Push(Box<dyn Scene>),
@@ -11,35 +24,342 @@ pub enum Transition {
 
 pub struct Context<'a> {
     pub universe: &'a Universe,
+    /// The world of the scene. This is the application's world, unless the application
+    /// gives every scene a world of its own.
     pub world: &'a mut World,
+    /// The application's world, when the scene has a world of its own.
+    /// Its resources are lent to `world` for as long as the context is alive.
+    pub shared_world: Option<&'a mut World>,
+    simulate_when_paused: Option<&'a mut bool>,
 }
 
 impl<'a> Context<'a> {
     pub fn new(universe: &'a Universe, world: &'a mut World) -> Self {
-        Self { universe, world }
+        Self {
+            universe,
+            world,
+            shared_world: None,
+            simulate_when_paused: None,
+        }
+    }
+
+    /// A copy of the application's [`Time`](../time/struct.Time.html) for the current frame.
+    pub fn time(&self) -> Time {
+        self.world
+            .resources
+            .get::<Time>()
+            .map_or_else(Time::default, |time| *time)
+    }
+
+    /// Queues changes to the application's primary window.
+    pub fn window(&self) -> WindowControl<'_> {
+        let id = self
+            .world
+            .resources
+            .get::<Windows>()
+            .and_then(|windows| windows.primary_id());
+        WindowControl::new(&self.world.resources, id)
+    }
+
+    /// Queues changes to the window `id`, such as the window of an `Event::Window`.
+    pub fn window_with_id(&self, id: WindowId) -> WindowControl<'_> {
+        WindowControl::new(&self.world.resources, Some(id))
+    }
+
+    /// Queues changes to the window named `name`, if it is open.
+    pub fn window_named(&self, name: &str) -> Option<WindowControl<'_>> {
+        let id = self.world.resources.get::<Windows>()?.id_of(name)?;
+        Some(WindowControl::new(&self.world.resources, Some(id)))
+    }
+
+    /// Opens a window once the current callback returns. It appears in the `Windows`
+    /// resource under the name set in `settings`.
+    pub fn open_window(&self, settings: WindowSettings) {
+        if let Some(mut commands) = self.world.resources.get_mut::<WindowCommands>() {
+            commands.push(WindowCommand::Open(settings));
+        }
+    }
+
+    /// The frame pacing settings the main loop currently uses.
+    pub fn frame_pacing(&self) -> FramePacingSettings {
+        self.world
+            .resources
+            .get::<FramePacing>()
+            .map_or_else(FramePacingSettings::default, |pacing| pacing.settings())
+    }
+
+    /// Changes how often the main loop runs frames, from the next frame on.
+    /// See [`FramePacing`](../pacing/struct.FramePacing.html).
+    pub fn set_frame_pacing(&self, settings: FramePacingSettings) -> Result<()> {
+        match self.world.resources.get_mut::<FramePacing>() {
+            Some(mut pacing) => pacing.set_settings(settings),
+            None => settings.validate(),
+        }
+    }
+
+    /// The interpolation alpha between the last two fixed updates.
+    /// See [`FixedTimestep::alpha`](../timestep/struct.FixedTimestep.html#method.alpha).
+    pub fn alpha(&self) -> f32 {
+        self.world
+            .resources
+            .get::<FixedTimestep>()
+            .map_or(0.0, |timestep| timestep.alpha())
+    }
+
+    /// Creates an empty world in the application's universe.
+    /// Entities created in it never share an id with entities of the scene worlds.
+    pub fn create_world(&self) -> World {
+        self.universe.create_world()
+    }
+
+    /// Moves every entity of `world` into the scene's world, keeping their ids.
+    /// `world` must have been created from the application's universe.
+    pub fn merge_world(&mut self, world: World) {
+        self.world.merge(world)
+    }
+
+    /// Moves every entity of the scene's world into `destination`, keeping their ids.
+    /// `destination` must have been created from the application's universe. The resources
+    /// stay with the scene.
+    pub fn move_world_to(&mut self, destination: &mut World) {
+        let mut world = mem::replace(self.world, self.universe.create_world());
+        mem::swap(&mut world.resources, &mut self.world.resources);
+        destination.merge(world);
+    }
+
+    /// Moves `entities` from `source` into the scene's world. Only whole worlds keep their
+    /// ids when moved, see [`merge_world`](#method.merge_world).
+    /// See [`ComponentRegistry::move_entities`](../registry/struct.ComponentRegistry.html#method.move_entities).
+    pub fn move_entities_from(&mut self, source: &mut World, entities: &[Entity]) -> EntityMap {
+        self.registry().move_entities(source, self.world, entities)
+    }
+
+    /// Moves `entities` from the scene's world into `destination`.
+    pub fn move_entities_to(&mut self, destination: &mut World, entities: &[Entity]) -> EntityMap {
+        self.registry()
+            .move_entities(self.world, destination, entities)
+    }
+
+    /// Clones `entities` of `source` into the scene's world.
+    /// See [`ComponentRegistry::clone_entities`](../registry/struct.ComponentRegistry.html#method.clone_entities).
+    pub fn clone_entities_from(&mut self, source: &World, entities: &[Entity]) -> EntityMap {
+        self.registry().clone_entities(source, self.world, entities)
+    }
+
+    /// Clones `entities` of the scene's world into `destination`.
+    pub fn clone_entities_to(&self, destination: &mut World, entities: &[Entity]) -> EntityMap {
+        self.registry()
+            .clone_entities(self.world, destination, entities)
+    }
+
+    /// Saves the serializable entities of the scene's world to `path`.
+    /// See [`serialization::save`](../serialization/fn.save.html).
+    pub fn save_world<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        serialization::save(&self.registry(), self.world, path)
+    }
+
+    /// Loads the entities saved in `path` into the scene's world.
+    /// See [`serialization::load`](../serialization/fn.load.html).
+    pub fn load_world<P>(&mut self, path: P) -> Result<Vec<Entity>>
+    where
+        P: AsRef<Path>,
+    {
+        serialization::load(&self.registry(), self.world, path)
+    }
+
+    /// Creates the entities of the prefab at `path`, relative to the assets directory, in the
+    /// scene's world. See [`Prefab::instantiate`](../prefab/struct.Prefab.html#method.instantiate).
+    pub fn instantiate_prefab<P>(&mut self, path: P) -> Result<Vec<Entity>>
+    where
+        P: AsRef<Path>,
+    {
+        let prefabs = self
+            .world
+            .resources
+            .get::<Prefabs>()
+            .map(|prefabs| prefabs.clone())
+            .unwrap_or_default();
+
+        prefabs
+            .load(path)?
+            .instantiate(&self.registry(), self.world)
+    }
+
+    /// Whether the scene keeps being simulated while it is paused under another scene.
+    pub fn simulates_when_paused(&self) -> bool {
+        self.simulate_when_paused
+            .as_ref()
+            .is_some_and(|simulate| **simulate)
+    }
+
+    /// Chooses whether the scene keeps being simulated while it is paused under another scene.
+    ///
+    /// A simulated scene has its own schedules executed every frame. If it has a world of its
+    /// own, the application's schedules are executed on that world as well.
+    pub fn set_simulate_when_paused(&mut self, simulate: bool) {
+        if let Some(simulate_when_paused) = self.simulate_when_paused.as_mut() {
+            **simulate_when_paused = simulate;
+        }
+    }
+
+    /// Reborrows the context, so that it can be handed to several callbacks in a row.
+    pub(crate) fn reborrow(&mut self) -> Context<'_> {
+        Context {
+            universe: self.universe,
+            world: &mut *self.world,
+            shared_world: self.shared_world.as_deref_mut(),
+            simulate_when_paused: self.simulate_when_paused.as_deref_mut(),
+        }
+    }
+
+    fn registry(&self) -> ComponentRegistry {
+        self.world
+            .resources
+            .get::<ComponentRegistry>()
+            .map(|registry| registry.clone())
+            .unwrap_or_default()
     }
 }
 
-//TODO: Add fixed update and late update
 pub trait Scene {
-    fn start(&mut self, context: Context) {}
-    fn stop(&mut self, context: Context) {}
-    fn pause(&mut self, context: Context) {}
-    fn resume(&mut self, context: Context) {}
-    fn handle_event(&mut self, context: Context, event: Event) -> Transition {
+    /// Adds the systems executed while the scene is active, after the application's systems
+    /// of the same stage.
+    /// Called once, right before `start`. The application stops with `Error::Stage` if a
+    /// system is added to a custom stage it did not add, and the scene is not started.
+    fn build_schedule<'a>(&mut self, builder: ScheduleBuilder<'a>) -> ScheduleBuilder<'a> {
+        builder
+    }
+    fn start(&mut self, _context: Context) {}
+    fn stop(&mut self, _context: Context) {}
+    fn pause(&mut self, _context: Context) {}
+    fn resume(&mut self, _context: Context) {}
+    fn handle_event(&mut self, _context: Context, _event: Event) -> Transition {
+        Transition::None
+    }
+    /// Called zero or more times per frame, once for every fixed timestep
+    /// accumulated since the previous frame, before `update`.
+    fn fixed_update(&mut self, _context: Context) -> Transition {
+        Transition::None
+    }
+    fn update(&mut self, _context: Context) -> Transition {
         Transition::None
     }
-    fn update(&mut self, context: Context) -> Transition {
+    /// Called once per frame after the `PostUpdate` stage has been executed.
+    fn late_update(&mut self, _context: Context) -> Transition {
         Transition::None
     }
-    fn pre_draw(&mut self, context: Context) {}
-    fn draw(&mut self, context: Context) {}
-    fn post_draw(&mut self, context: Context) {}
+    fn pre_draw(&mut self, _context: Context) {}
+    fn draw(&mut self, _context: Context) {}
+    fn post_draw(&mut self, _context: Context) {}
+}
+
+struct SceneEntry {
+    scene: Box<dyn Scene>,
+    world: Option<World>,
+    schedules: StageSchedules,
+    simulate_when_paused: bool,
+}
+
+impl SceneEntry {
+    fn new(scene: Box<dyn Scene>, universe: &Universe, world_per_scene: bool) -> Self {
+        Self {
+            scene,
+            world: if world_per_scene {
+                Some(universe.create_world())
+            } else {
+                None
+            },
+            schedules: StageSchedules::default(),
+            simulate_when_paused: false,
+        }
+    }
+
+    /// Builds the schedules of the scene and starts it. The scene is not started when its
+    /// schedules cannot be built.
+    fn start(&mut self, context: Context) -> Result<()> {
+        let schedules = self.invoke(context, |scene, mut context| {
+            let schedules = scene
+                .build_schedule(ScheduleBuilder::new(context.world))
+                .build();
+            if schedules.is_ok() {
+                scene.start(context.reborrow());
+            }
+            schedules
+        })?;
+        self.schedules = schedules;
+        Ok(())
+    }
+
+    /// Invokes `callback` with a context for the scene.
+    /// A scene with a world of its own borrows the resources of the application's world.
+    fn invoke<F, R>(&mut self, context: Context, callback: F) -> R
+    where
+        F: FnOnce(&mut dyn Scene, Context) -> R,
+    {
+        let Context {
+            universe, world, ..
+        } = context;
+        let SceneEntry {
+            scene,
+            world: scene_world,
+            simulate_when_paused,
+            ..
+        } = self;
+
+        match scene_world {
+            Some(scene_world) => {
+                let swapped = SwappedResources::new(scene_world, world);
+                callback(
+                    scene.as_mut(),
+                    Context {
+                        universe,
+                        world: &mut *swapped.scene_world,
+                        shared_world: Some(&mut *swapped.world),
+                        simulate_when_paused: Some(simulate_when_paused),
+                    },
+                )
+            }
+            None => callback(
+                scene.as_mut(),
+                Context {
+                    universe,
+                    world,
+                    shared_world: None,
+                    simulate_when_paused: Some(simulate_when_paused),
+                },
+            ),
+        }
+    }
+}
+
+/// The resources of the application's world lent to a scene's world. They are given back
+/// when dropped, so a scene or system that panics does not keep them.
+struct SwappedResources<'a> {
+    scene_world: &'a mut World,
+    world: &'a mut World,
+}
+
+impl<'a> SwappedResources<'a> {
+    fn new(scene_world: &'a mut World, world: &'a mut World) -> Self {
+        mem::swap(&mut scene_world.resources, &mut world.resources);
+        Self { scene_world, world }
+    }
+}
+
+impl Drop for SwappedResources<'_> {
+    fn drop(&mut self) {
+        mem::swap(&mut self.scene_world.resources, &mut self.world.resources);
+    }
 }
 
 pub struct SceneManager {
-    scenes: Vec<Box<dyn Scene>>,
+    scenes: Vec<SceneEntry>,
+    initial_scene: Option<Box<dyn Scene>>,
     is_running: bool,
+    world_per_scene: bool,
 }
 
 impl SceneManager {
@@ -48,47 +368,136 @@ impl SceneManager {
         S: Scene + 'static,
     {
         Self {
-            scenes: vec![Box::new(initial_scene)],
+            scenes: vec![],
+            initial_scene: Some(Box::new(initial_scene)),
             is_running: false,
+            world_per_scene: false,
+        }
+    }
+
+    /// Gives every scene pushed on the stack an empty world of its own, created from the
+    /// application's universe, instead of sharing the application's world.
+    ///
+    /// Scene worlds hold entities only. The resources of the application's world are lent
+    /// to a scene world whenever a scene callback or the systems run on it.
+    pub fn with_world_per_scene(mut self, world_per_scene: bool) -> Self {
+        self.world_per_scene = world_per_scene;
+        self
+    }
+
+    pub fn has_world_per_scene(&self) -> bool {
+        self.world_per_scene
+    }
+
+    /// Starts the initial scene. Fails if the scene added systems to a stage the application
+    /// did not add, in which case the manager is left without scenes.
+    pub(crate) fn initialize(&mut self, context: Context) -> Result<()> {
+        if let Some(scene) = self.initial_scene.take() {
+            self.start(scene, context)?;
+            self.is_running = true
         }
+        Ok(())
     }
 
-    pub(crate) fn initialize(&mut self, context: Context) {
-        self.scenes.last_mut().unwrap().start(context);
-        self.is_running = true
+    /// Pushes `scene` on the stack and starts it. The scene is removed again if it fails to
+    /// start.
+    fn start(&mut self, scene: Box<dyn Scene>, context: Context) -> Result<()> {
+        self.scenes.push(SceneEntry::new(
+            scene,
+            context.universe,
+            self.world_per_scene,
+        ));
+        let result = self.scenes.last_mut().unwrap().start(context);
+        if result.is_err() {
+            self.scenes.pop();
+        }
+        result
+    }
+
+    pub(crate) fn fixed_update(&mut self, context: Context) -> Transition {
+        match self.scenes.last_mut() {
+            Some(entry) => entry.invoke(context, |scene, context| scene.fixed_update(context)),
+            None => Transition::None,
+        }
     }
 
     pub(crate) fn update(&mut self, context: Context) -> Transition {
-        let Context { universe, world } = context;
+        match self.scenes.last_mut() {
+            Some(entry) => entry.invoke(context, |scene, context| scene.update(context)),
+            None => Transition::None,
+        }
+    }
 
+    pub(crate) fn late_update(&mut self, context: Context) -> Transition {
         match self.scenes.last_mut() {
-            Some(scene) => scene.update(Context::new(universe, world)),
+            Some(entry) => entry.invoke(context, |scene, context| scene.late_update(context)),
             None => Transition::None,
         }
     }
 
-    pub(crate) fn handle_event(&mut self, context: Context, event: Event) -> Transition {
-        let Context { universe, world } = context;
+    pub(crate) fn draw(&mut self, context: Context) {
+        if let Some(entry) = self.scenes.last_mut() {
+            entry.invoke(context, |scene, mut context| {
+                scene.pre_draw(context.reborrow());
+                scene.draw(context.reborrow());
+                scene.post_draw(context);
+            })
+        }
+    }
 
+    pub(crate) fn handle_event(&mut self, context: Context, event: Event) -> Transition {
         match self.scenes.last_mut() {
-            Some(scene) => scene.handle_event(Context::new(universe, world), event),
+            Some(entry) => {
+                entry.invoke(context, |scene, context| scene.handle_event(context, event))
+            }
             None => Transition::None,
         }
     }
 
     pub(crate) fn pause(&mut self, context: Context) {
-        let Context { universe, world } = context;
-
-        if let Some(scene) = self.scenes.last_mut() {
-            scene.pause(Context::new(universe, world))
+        if let Some(entry) = self.scenes.last_mut() {
+            entry.invoke(context, |scene, context| scene.pause(context))
         }
     }
 
     pub(crate) fn resume(&mut self, context: Context) {
-        let Context { universe, world } = context;
+        if let Some(entry) = self.scenes.last_mut() {
+            entry.invoke(context, |scene, context| scene.resume(context))
+        }
+    }
+
+    /// Executes the `stage` schedule of `schedules`, the application's schedules, and the
+    /// `stage` schedules of the scenes that are simulated this frame: the active scene and the
+    /// paused scenes that keep simulating, from the bottom of the stack to the top.
+    ///
+    /// When scenes share the application's world, the application's schedule is executed once
+    /// on it, followed by the scenes' schedules. Otherwise both are executed on the world of
+    /// every simulated scene.
+    pub(crate) fn execute(
+        &mut self,
+        world: &mut World,
+        stage: Stage,
+        schedules: &mut StageSchedules,
+    ) {
+        if !self.world_per_scene {
+            schedules.execute(stage, world);
+        }
+
+        let top = self.scenes.len().saturating_sub(1);
 
-        if let Some(scene) = self.scenes.last_mut() {
-            scene.resume(Context::new(universe, world))
+        for (index, entry) in self.scenes.iter_mut().enumerate() {
+            if index != top && !entry.simulate_when_paused {
+                continue;
+            }
+
+            match entry.world.as_mut() {
+                Some(scene_world) => {
+                    let swapped = SwappedResources::new(scene_world, world);
+                    schedules.execute(stage, swapped.scene_world);
+                    entry.schedules.execute(stage, swapped.scene_world);
+                }
+                None => entry.schedules.execute(stage, world),
+            }
         }
     }
 
@@ -96,29 +505,445 @@ impl SceneManager {
         self.is_running
     }
 
-    pub(crate) fn push(&mut self, scene: Box<dyn Scene>, context: Context) {
-        let Context { universe, world } = context;
+    /// Pauses the scene at the top of the stack and starts `scene` on top of it.
+    /// Fails like [`initialize`](#method.initialize), leaving the paused scene on top.
+    pub(crate) fn push(&mut self, scene: Box<dyn Scene>, context: Context) -> Result<()> {
+        let Context {
+            universe, world, ..
+        } = context;
 
         if let Some(current) = self.scenes.last_mut() {
-            current.pause(Context::new(universe, world))
+            current.invoke(Context::new(universe, world), |scene, context| {
+                scene.pause(context)
+            })
         }
 
-        self.scenes.push(scene);
-        self.scenes
-            .last_mut()
-            .unwrap()
-            .start(Context::new(universe, world))
+        self.start(scene, Context::new(universe, world))
+    }
+
+    /// Stops the scene at the top of the stack and replaces it with `scene`.
+    /// Fails like [`initialize`](#method.initialize), without restoring the stopped scene.
+    pub(crate) fn switch(&mut self, scene: Box<dyn Scene>, context: Context) -> Result<()> {
+        let Context {
+            universe, world, ..
+        } = context;
+
+        if let Some(mut current) = self.scenes.pop() {
+            current.invoke(Context::new(universe, world), |scene, context| {
+                scene.stop(context)
+            })
+        }
+
+        self.start(scene, Context::new(universe, world))
+    }
+
+    /// Stops the scene at the top of the stack and resumes the one below it.
+    /// Popping the last scene leaves the manager in a non-running state.
+    pub(crate) fn pop(&mut self, context: Context) {
+        let Context {
+            universe, world, ..
+        } = context;
+
+        if let Some(mut current) = self.scenes.pop() {
+            current.invoke(Context::new(universe, world), |scene, context| {
+                scene.stop(context)
+            })
+        }
+
+        match self.scenes.last_mut() {
+            Some(entry) => entry.invoke(Context::new(universe, world), |scene, context| {
+                scene.resume(context)
+            }),
+            None => self.is_running = false,
+        }
     }
 
     pub(crate) fn stop(&mut self, context: Context) {
         if self.is_running {
-            let Context { universe, world } = context;
+            let Context {
+                universe, world, ..
+            } = context;
 
-            while let Some(mut scene) = self.scenes.pop() {
-                scene.stop(Context::new(universe, world))
+            while let Some(mut entry) = self.scenes.pop() {
+                entry.invoke(Context::new(universe, world), |scene, context| {
+                    scene.stop(context)
+                })
             }
 
             self.is_running = false;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::StageSteps;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct RecordingScene {
+        name: &'static str,
+        log: Log,
+    }
+
+    impl RecordingScene {
+        fn new(name: &'static str, log: &Log) -> Self {
+            Self {
+                name,
+                log: log.clone(),
+            }
+        }
+
+        fn record(&self, callback: &str) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{}::{}", self.name, callback))
+        }
+    }
+
+    impl Scene for RecordingScene {
+        fn start(&mut self, _context: Context) {
+            self.record("start")
+        }
+
+        fn stop(&mut self, _context: Context) {
+            self.record("stop")
+        }
+
+        fn pause(&mut self, _context: Context) {
+            self.record("pause")
+        }
+
+        fn resume(&mut self, _context: Context) {
+            self.record("resume")
+        }
+    }
+
+    fn drain(log: &Log) -> Vec<String> {
+        log.lock().unwrap().drain(..).collect()
+    }
+
+    #[test]
+    fn push_pauses_current_and_starts_new() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let log = Log::default();
+
+        let mut scene_manager = SceneManager::new(RecordingScene::new("menu", &log));
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        scene_manager
+            .push(
+                Box::new(RecordingScene::new("gameplay", &log)),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+
+        assert_eq!(
+            drain(&log),
+            vec!["menu::start", "menu::pause", "gameplay::start"]
+        );
+    }
+
+    #[test]
+    fn switch_stops_current_and_starts_new() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let log = Log::default();
+
+        let mut scene_manager = SceneManager::new(RecordingScene::new("menu", &log));
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        drain(&log);
+
+        scene_manager
+            .switch(
+                Box::new(RecordingScene::new("gameplay", &log)),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+
+        assert_eq!(drain(&log), vec!["menu::stop", "gameplay::start"]);
+        assert!(scene_manager.is_running());
+
+        scene_manager.stop(Context::new(&universe, &mut world));
+        assert_eq!(drain(&log), vec!["gameplay::stop"]);
+    }
+
+    #[test]
+    fn switch_leaves_paused_scenes_untouched() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let log = Log::default();
+
+        let mut scene_manager = SceneManager::new(RecordingScene::new("gameplay", &log));
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        scene_manager
+            .push(
+                Box::new(RecordingScene::new("pause", &log)),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+        drain(&log);
+
+        scene_manager
+            .switch(
+                Box::new(RecordingScene::new("options", &log)),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+        scene_manager.pop(Context::new(&universe, &mut world));
+
+        assert_eq!(
+            drain(&log),
+            vec![
+                "pause::stop",
+                "options::start",
+                "options::stop",
+                "gameplay::resume"
+            ]
+        );
+    }
+
+    #[test]
+    fn pop_stops_top_and_resumes_below() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let log = Log::default();
+
+        let mut scene_manager = SceneManager::new(RecordingScene::new("gameplay", &log));
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        scene_manager
+            .push(
+                Box::new(RecordingScene::new("pause", &log)),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+        drain(&log);
+
+        scene_manager.pop(Context::new(&universe, &mut world));
+
+        assert_eq!(drain(&log), vec!["pause::stop", "gameplay::resume"]);
+        assert!(scene_manager.is_running());
+    }
+
+    #[test]
+    fn pop_last_scene_stops_running() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let log = Log::default();
+
+        let mut scene_manager = SceneManager::new(RecordingScene::new("menu", &log));
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        drain(&log);
+
+        scene_manager.pop(Context::new(&universe, &mut world));
+
+        assert_eq!(drain(&log), vec!["menu::stop"]);
+        assert!(!scene_manager.is_running());
+
+        // Nothing left to stop once the stack has been emptied.
+        scene_manager.stop(Context::new(&universe, &mut world));
+        assert!(drain(&log).is_empty());
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Marker(u32);
+
+    struct StartScene<F>(F);
+
+    impl<F> Scene for StartScene<F>
+    where
+        F: FnMut(Context),
+    {
+        fn start(&mut self, context: Context) {
+            (self.0)(context)
+        }
+    }
+
+    fn count_markers(world: &World) -> usize {
+        use crate::ecs::query::{IntoQuery, Read};
+
+        <Read<Marker>>::query().iter_immutable(world).count()
+    }
+
+    #[test]
+    fn world_per_scene_isolates_entities_and_shares_resources() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        world.resources.insert(Marker(7));
+
+        let mut scene_manager = SceneManager::new(StartScene(|context: Context| {
+            context.world.insert((), vec![(Marker(0),)]);
+        }))
+        .with_world_per_scene(true);
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+
+        let observed = Arc::new(Mutex::new(None));
+        let pause_observed = observed.clone();
+        scene_manager
+            .push(
+                Box::new(StartScene(move |context: Context| {
+                    let markers = count_markers(context.world);
+                    let resource = *context.world.resources.get::<Marker>().unwrap();
+                    *pause_observed.lock().unwrap() = Some((markers, resource));
+                })),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+
+        assert_eq!(*observed.lock().unwrap(), Some((0, Marker(7))));
+        assert_eq!(count_markers(&world), 0);
+        assert!(world.resources.get::<Marker>().is_some());
+    }
+
+    #[test]
+    fn simulates_active_and_opted_in_paused_worlds() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+
+        let mut scene_manager = SceneManager::new(StartScene(|mut context: Context| {
+            context.world.insert((), vec![(Marker(0),)]);
+            context.set_simulate_when_paused(true);
+        }))
+        .with_world_per_scene(true);
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+        scene_manager
+            .push(
+                Box::new(StartScene(|context: Context| {
+                    context.world.insert((), vec![(Marker(1),), (Marker(1),)]);
+                })),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+        scene_manager
+            .push(
+                Box::new(StartScene(|context: Context| {
+                    context
+                        .world
+                        .insert((), vec![(Marker(2),), (Marker(2),), (Marker(2),)]);
+                })),
+                Context::new(&universe, &mut world),
+            )
+            .unwrap();
+
+        let simulated = Arc::new(Mutex::new(vec![]));
+        let counts = simulated.clone();
+        let mut steps = StageSteps::default();
+        steps
+            .get_mut(Stage::Update)
+            .add_thread_local_fn(move |world| counts.lock().unwrap().push(count_markers(world)));
+        let mut schedules = steps.build(&[]);
+        scene_manager.execute(&mut world, Stage::PreUpdate, &mut schedules);
+        assert!(simulated.lock().unwrap().is_empty());
+        scene_manager.execute(&mut world, Stage::Update, &mut schedules);
+
+        assert_eq!(*simulated.lock().unwrap(), vec![1, 3]);
+    }
+
+    #[test]
+    fn moves_entities_between_scene_and_shared_worlds() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut registry = ComponentRegistry::new();
+        registry.register::<Marker>();
+        world.resources.insert(registry);
+
+        let mut scene_manager = SceneManager::new(StartScene(|mut context: Context| {
+            let entity = context.world.insert((), vec![(Marker(3),)])[0];
+            let shared_world = context.shared_world.take().unwrap();
+            let entity_map = context.move_entities_to(shared_world, &[entity]);
+            assert_eq!(entity_map.len(), 1);
+        }))
+        .with_world_per_scene(true);
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+
+        assert_eq!(count_markers(&world), 1);
+    }
+
+    #[test]
+    fn moves_whole_scene_worlds_keeping_entity_ids() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        world.resources.insert(ComponentRegistry::new());
+        let moved = Arc::new(Mutex::new(Vec::new()));
+        let scene_moved = moved.clone();
+
+        let mut scene_manager = SceneManager::new(StartScene(move |mut context: Context| {
+            let entities = context.world.insert((), vec![(Marker(1),), (Marker(2),)]);
+            scene_moved.lock().unwrap().extend_from_slice(entities);
+            let shared_world = context.shared_world.take().unwrap();
+            context.move_world_to(shared_world);
+            assert_eq!(count_markers(context.world), 0);
+            assert!(context.world.resources.get::<ComponentRegistry>().is_some());
+        }))
+        .with_world_per_scene(true);
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+
+        let moved = moved.lock().unwrap();
+        assert_eq!(moved.len(), 2);
+        for (&entity, expected) in moved.iter().zip(1..) {
+            assert_eq!(world.get_component::<Marker>(entity).unwrap().0, expected);
+        }
+    }
+
+    #[test]
+    fn gives_resources_back_when_a_scene_panics() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        world.resources.insert(ComponentRegistry::new());
+
+        let mut scene_manager =
+            SceneManager::new(StartScene(|_: Context| panic!("start"))).with_world_per_scene(true);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scene_manager.initialize(Context::new(&universe, &mut world))
+        }));
+
+        assert!(result.is_err());
+        assert!(world.resources.get::<ComponentRegistry>().is_some());
+    }
+
+    #[test]
+    fn gives_resources_back_when_a_system_panics() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        world.resources.insert(Marker(7));
+
+        let mut scene_manager =
+            SceneManager::new(StartScene(|_: Context| {})).with_world_per_scene(true);
+        scene_manager
+            .initialize(Context::new(&universe, &mut world))
+            .unwrap();
+
+        let mut steps = StageSteps::default();
+        steps
+            .get_mut(Stage::Update)
+            .add_thread_local_fn(|_| panic!("update"));
+        let mut schedules = steps.build(&[]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            scene_manager.execute(&mut world, Stage::Update, &mut schedules)
+        }));
+
+        assert!(result.is_err());
+        assert!(world.resources.get::<Marker>().is_some());
+    }
+}
@@ -0,0 +1,474 @@
+//! Building system schedules and the stages of the frame they are executed in.
+//!
+//! Systems are added to a [`Stage`](enum.Stage.html), `Stage::Update` unless another one is
+//! given. The built-in stages are executed at fixed points of every frame, in the order they
+//! are declared in. Custom stages are added with `ApplicationBuilder::with_stage_before` and
+//! `ApplicationBuilder::with_stage_after`, and are executed right before or after the stage
+//! they are placed next to, at the same point of the frame.
+
+use crate::core::error::{Error, Result};
+use crate::core::event::EventRegistry;
+use crate::ecs::resource::ResourceTypeId;
+use crate::ecs::schedule::{Runnable, Schedulable, Schedule};
+use crate::ecs::system::SystemBuilder;
+use crate::ecs::world::World;
+use std::fmt;
+
+/// A group of systems executed at a given point of the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// At the start of the frame, before the fixed updates.
+    First,
+    /// After the fixed updates, before `Scene::update`.
+    PreUpdate,
+    /// After `Scene::update`.
+    Update,
+    /// After `Update`, before `Scene::late_update`.
+    PostUpdate,
+    /// At the start of the drawn frame.
+    PreRender,
+    /// After `PreRender`, before the draw callbacks of the active scene.
+    Render,
+    /// After the draw callbacks, at the end of the frame.
+    Last,
+    /// A stage added by the application, identified by its name.
+    Custom(&'static str),
+}
+
+impl Stage {
+    /// The built-in stages, in execution order.
+    pub const BUILT_IN: [Stage; 7] = [
+        Stage::First,
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::PreRender,
+        Stage::Render,
+        Stage::Last,
+    ];
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Custom(name) => f.write_str(name),
+            built_in => fmt::Debug::fmt(built_in, f),
+        }
+    }
+}
+
+/// The stages of the application in execution order. Also kept as a resource, so that
+/// the schedules of the scenes can be checked against it.
+#[derive(Debug, Clone)]
+pub(crate) struct Stages {
+    stages: Vec<StageEntry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StageEntry {
+    stage: Stage,
+    /// The built-in stage the stage is executed with.
+    point: Stage,
+}
+
+impl Default for Stages {
+    fn default() -> Self {
+        Self {
+            stages: Stage::BUILT_IN
+                .iter()
+                .map(|&stage| StageEntry {
+                    stage,
+                    point: stage,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Stages {
+    /// Adds the custom `stage` right before `anchor`, or right after it if `after` is set.
+    pub(crate) fn insert(&mut self, stage: Stage, anchor: Stage, after: bool) -> Result<()> {
+        let error = |message: String| Error::Stage {
+            stage: stage.to_string(),
+            message,
+        };
+
+        if !matches!(stage, Stage::Custom(_)) {
+            return Err(error("is built in and cannot be added".to_string()));
+        }
+        if self.contains(stage) {
+            return Err(error("was added more than once".to_string()));
+        }
+        let index = self
+            .stages
+            .iter()
+            .position(|entry| entry.stage == anchor)
+            .ok_or_else(|| {
+                error(format!(
+                    "is placed next to `{}`, which was not added",
+                    anchor
+                ))
+            })?;
+
+        let point = self.stages[index].point;
+        let index = if after { index + 1 } else { index };
+        self.stages.insert(index, StageEntry { stage, point });
+        Ok(())
+    }
+
+    pub(crate) fn contains(&self, stage: Stage) -> bool {
+        self.stages.iter().any(|entry| entry.stage == stage)
+    }
+
+    /// The stages executed at the point of the frame of the built-in stage `point`.
+    pub(crate) fn at(&self, point: Stage) -> impl Iterator<Item = Stage> + '_ {
+        self.stages
+            .iter()
+            .filter(move |entry| entry.point == point)
+            .map(|entry| entry.stage)
+    }
+}
+
+/// Builds a scene's schedules with the same helpers `ApplicationBuilder` offers.
+///
+/// The world handed to the system builder functions is the scene's world,
+/// with the application's resources available in `world.resources`. Systems can only be
+/// added to the stages of the application. Debug builds panic when the schedules are built
+/// if a system was added to a custom stage the application does not have; release builds
+/// never execute it.
+pub struct ScheduleBuilder<'a> {
+    world: &'a mut World,
+    steps: StageSteps,
+}
+
+impl<'a> ScheduleBuilder<'a> {
+    pub(crate) fn new(world: &'a mut World) -> Self {
+        Self {
+            world,
+            steps: StageSteps::default(),
+        }
+    }
+
+    pub fn with_system<B>(self, name: &'static str, builder_func: B) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Schedulable>,
+    {
+        self.with_system_in_stage(Stage::Update, name, builder_func)
+    }
+
+    pub fn with_system_in_stage<B>(
+        mut self,
+        stage: Stage,
+        name: &'static str,
+        mut builder_func: B,
+    ) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Schedulable>,
+    {
+        self.steps
+            .get_mut(stage)
+            .add_system(builder_func(self.world, SystemBuilder::new(name)));
+        self
+    }
+
+    pub fn with_thread_local_system<B>(self, name: &'static str, builder_func: B) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Runnable>,
+    {
+        self.with_thread_local_system_in_stage(Stage::Update, name, builder_func)
+    }
+
+    pub fn with_thread_local_system_in_stage<B>(
+        mut self,
+        stage: Stage,
+        name: &'static str,
+        mut builder_func: B,
+    ) -> Self
+    where
+        B: FnMut(&mut World, SystemBuilder) -> Box<dyn Runnable>,
+    {
+        self.steps
+            .get_mut(stage)
+            .add_thread_local(builder_func(self.world, SystemBuilder::new(name)));
+        self
+    }
+
+    pub fn with_thread_local_fn<F>(self, func: F) -> Self
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.with_thread_local_fn_in_stage(Stage::Update, func)
+    }
+
+    pub fn with_thread_local_fn_in_stage<F>(mut self, stage: Stage, func: F) -> Self
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.steps.get_mut(stage).add_thread_local_fn(func);
+        self
+    }
+
+    /// Flushes the command buffers of the systems added to `Stage::Update` so far. Same as
+    /// `flush_stage(Stage::Update)`.
+    pub fn flush(self) -> Self {
+        self.flush_stage(Stage::Update)
+    }
+
+    /// Flushes the command buffers of the systems added to `stage` so far.
+    pub fn flush_stage(mut self, stage: Stage) -> Self {
+        self.steps.get_mut(stage).flush();
+        self
+    }
+
+    /// Builds a schedule for every stage a system was added to. Fails if one of them is a
+    /// custom stage the application did not add.
+    pub(crate) fn build(self) -> Result<StageSchedules> {
+        let missing = self
+            .world
+            .resources
+            .get::<Stages>()
+            .and_then(|stages| self.steps.stages().find(|&stage| !stages.contains(stage)));
+        if let Some(stage) = missing {
+            return Err(Error::Stage {
+                stage: stage.to_string(),
+                message: "has systems of a scene but was not added".to_string(),
+            });
+        }
+
+        let events = self
+            .world
+            .resources
+            .get::<EventRegistry>()
+            .map(|registry| registry.resource_ids())
+            .unwrap_or_default();
+        Ok(self.steps.build(&events))
+    }
+}
+
+/// The steps added to every stage.
+#[derive(Default)]
+pub(crate) struct StageSteps {
+    stages: Vec<(Stage, Steps)>,
+}
+
+impl StageSteps {
+    pub(crate) fn get_mut(&mut self, stage: Stage) -> &mut Steps {
+        let index = match self.stages.iter().position(|(added, _)| *added == stage) {
+            Some(index) => index,
+            None => {
+                self.stages.push((stage, Steps::default()));
+                self.stages.len() - 1
+            }
+        };
+        &mut self.stages[index].1
+    }
+
+    /// The stages a system was added to.
+    pub(crate) fn stages(&self) -> impl Iterator<Item = Stage> + '_ {
+        self.stages
+            .iter()
+            .filter(|(_, steps)| !steps.is_empty())
+            .map(|(stage, _)| *stage)
+    }
+
+    /// Builds a schedule for every stage a system was added to.
+    pub(crate) fn build(self, events: &[ResourceTypeId]) -> StageSchedules {
+        StageSchedules {
+            schedules: self
+                .stages
+                .into_iter()
+                .filter(|(_, steps)| !steps.is_empty())
+                .map(|(stage, steps)| (stage, steps.build(events)))
+                .collect(),
+        }
+    }
+}
+
+/// The schedules of the stages systems were added to.
+#[derive(Default)]
+pub(crate) struct StageSchedules {
+    schedules: Vec<(Stage, Schedule)>,
+}
+
+impl StageSchedules {
+    /// Executes the schedule of `stage` on `world`, if the stage has one.
+    pub(crate) fn execute(&mut self, stage: Stage, world: &mut World) {
+        if let Some((_, schedule)) = self
+            .schedules
+            .iter_mut()
+            .find(|(scheduled, _)| *scheduled == stage)
+        {
+            schedule.execute(world);
+        }
+    }
+}
+
+enum Step {
+    System(Box<dyn Schedulable>),
+    ThreadLocal(Box<dyn FnMut(&mut World)>),
+    Flush,
+}
+
+/// The steps of a schedule in the order they were added.
+#[derive(Default)]
+pub(crate) struct Steps {
+    steps: Vec<Step>,
+}
+
+impl Steps {
+    pub(crate) fn add_system(&mut self, system: Box<dyn Schedulable>) {
+        self.steps.push(Step::System(system));
+    }
+
+    pub(crate) fn add_thread_local(&mut self, system: Box<dyn Runnable>) {
+        self.add_thread_local_fn(move |world| system.run(world));
+    }
+
+    pub(crate) fn add_thread_local_fn<F>(&mut self, func: F)
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.steps.push(Step::ThreadLocal(Box::new(func)));
+    }
+
+    pub(crate) fn flush(&mut self) {
+        self.steps.push(Step::Flush);
+    }
+
+    /// Whether no system was added. Flushes alone do not count.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.steps.iter().all(|step| matches!(step, Step::Flush))
+    }
+
+    /// Builds the schedule. Within every run of systems between thread-local steps and
+    /// flushes, the systems that write one of the `events` resources run before the systems
+    /// that only read it.
+    pub(crate) fn build(self, events: &[ResourceTypeId]) -> Schedule {
+        let mut builder = Schedule::builder();
+        let mut systems = Vec::new();
+        for step in self.steps {
+            if let Step::System(system) = step {
+                systems.push(system);
+                continue;
+            }
+
+            for system in order_by_events(std::mem::take(&mut systems), events) {
+                builder = builder.add_system(system);
+            }
+            builder = match step {
+                Step::ThreadLocal(func) => builder.add_thread_local_fn(func),
+                _ => builder.flush(),
+            };
+        }
+
+        for system in order_by_events(systems, events) {
+            builder = builder.add_system(system);
+        }
+        builder.build()
+    }
+}
+
+/// Orders `systems` so that writers of the `events` resources come before their readers,
+/// keeping the order they were added in otherwise. Systems that depend on each other in a
+/// cycle keep the order they were added in.
+fn order_by_events(
+    systems: Vec<Box<dyn Schedulable>>,
+    events: &[ResourceTypeId],
+) -> Vec<Box<dyn Schedulable>> {
+    let accesses = |access: &[ResourceTypeId]| -> Vec<ResourceTypeId> {
+        access
+            .iter()
+            .filter(|id| events.contains(id))
+            .copied()
+            .collect()
+    };
+    let writes: Vec<_> = systems
+        .iter()
+        .map(|system| accesses(system.writes().0))
+        .collect();
+    let reads: Vec<_> = systems
+        .iter()
+        .map(|system| accesses(system.reads().0))
+        .collect();
+
+    let waits_for = |reader: usize, writer: usize| {
+        reader != writer
+            && reads[reader]
+                .iter()
+                .any(|id| writes[writer].contains(id) && !writes[reader].contains(id))
+    };
+
+    let mut systems: Vec<_> = systems.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(systems.len());
+    while ordered.len() < systems.len() {
+        let pending = |index: &usize| systems[*index].is_some();
+        let next = (0..systems.len())
+            .filter(pending)
+            .find(|&reader| {
+                !(0..systems.len())
+                    .filter(pending)
+                    .any(|writer| waits_for(reader, writer))
+            })
+            .or_else(|| (0..systems.len()).find(pending))
+            .unwrap();
+        ordered.push(systems[next].take().unwrap());
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_custom_stages_next_to_their_anchor() {
+        let mut stages = Stages::default();
+        stages
+            .insert(Stage::Custom("physics"), Stage::Update, false)
+            .unwrap();
+        stages
+            .insert(Stage::Custom("animation"), Stage::Custom("physics"), true)
+            .unwrap();
+        stages
+            .insert(Stage::Custom("cleanup"), Stage::Last, true)
+            .unwrap();
+
+        assert_eq!(
+            stages.at(Stage::Update).collect::<Vec<_>>(),
+            vec![
+                Stage::Custom("physics"),
+                Stage::Custom("animation"),
+                Stage::Update
+            ]
+        );
+        assert_eq!(
+            stages.at(Stage::Last).collect::<Vec<_>>(),
+            vec![Stage::Last, Stage::Custom("cleanup")]
+        );
+        assert_eq!(
+            stages.at(Stage::First).collect::<Vec<_>>(),
+            vec![Stage::First]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_stages() {
+        let mut stages = Stages::default();
+        stages
+            .insert(Stage::Custom("physics"), Stage::Update, false)
+            .unwrap();
+
+        let cases = [
+            (Stage::Render, Stage::Update),
+            (Stage::Custom("physics"), Stage::PostUpdate),
+            (Stage::Custom("audio"), Stage::Custom("sound")),
+        ];
+        for (stage, anchor) in cases {
+            match stages.insert(stage, anchor, true) {
+                Err(Error::Stage { stage: name, .. }) => assert_eq!(name, stage.to_string()),
+                Err(error) => panic!("unexpected error {}", error),
+                Ok(()) => panic!("added invalid stage {}", stage),
+            }
+        }
+    }
+}
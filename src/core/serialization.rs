@@ -0,0 +1,440 @@
+//! Saving worlds to files and loading them back.
+//!
+//! Only components registered with
+//! [`ComponentRegistry::register_serializable`](../registry/struct.ComponentRegistry.html#method.register_serializable)
+//! are written, under the name they were registered with. The file format is picked by
+//! extension, like settings files:
+//!
+//! ```yaml
+//! entities:
+//!   - id: 0
+//!     components:
+//!       position: {x: 1.0, y: 2.0}
+//!   - id: 1
+//!     components:
+//!       follow: 0
+//! ```
+//!
+//! Entity ids are local to the file. Components referring to other entities serialize those
+//! references with the [`entity`](entity/index.html) module, and get the entities created by
+//! `load` back.
+
+use crate::core::application::settings::Format;
+use crate::core::error::{Error, Result};
+use crate::core::vfs::crc32;
+use crate::ecs::entity::Entity;
+use crate::ecs::world::World;
+use crate::registry::{BoxedComponent, ComponentRegistry};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+thread_local! {
+    static SAVED_IDS: RefCell<Option<HashMap<Entity, u64>>> = const { RefCell::new(None) };
+    static LOADED_ENTITIES: RefCell<Option<HashMap<u64, Entity>>> = const { RefCell::new(None) };
+}
+
+/// Serde functions for `Entity` fields of serializable components, to be used with
+/// `#[serde(with = "crius::serialization::entity")]`.
+///
+/// Saving fails when a component refers to an entity that is not saved along with it.
+pub mod entity {
+    use super::{LOADED_ENTITIES, SAVED_IDS};
+    use crate::ecs::entity::Entity;
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(entity: &Entity, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let id = SAVED_IDS.with(|ids| ids.borrow().as_ref()?.get(entity).copied());
+
+        match id {
+            Some(id) => serializer.serialize_u64(id),
+            None => Err(S::Error::custom(format!(
+                "entity {} is not part of the saved entities",
+                entity
+            ))),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Entity, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let id = u64::deserialize(deserializer)?;
+        let entity = LOADED_ENTITIES.with(|entities| entities.borrow().as_ref()?.get(&id).copied());
+
+        entity.ok_or_else(|| D::Error::custom(format!("no entity with id {}", id)))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedWorld {
+    entities: Vec<SavedEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntity {
+    id: u64,
+    #[serde(default)]
+    components: BTreeMap<String, Value>,
+}
+
+/// Saves every entity of `world` with at least one serializable component to `path`.
+pub fn save<P>(registry: &ComponentRegistry, world: &World, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let entities = registry.serializable_entities(world);
+    save_entities(registry, world, &entities, path)
+}
+
+/// Saves `entities` of `world` to `path`. Entities that are not alive are left out.
+pub fn save_entities<P>(
+    registry: &ComponentRegistry,
+    world: &World,
+    entities: &[Entity],
+    path: P,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let format = format_of(path)?;
+
+    let mut ids = HashMap::with_capacity(entities.len());
+    let mut saved = Vec::with_capacity(entities.len());
+    for &entity in entities {
+        if world.is_alive(entity) && !ids.contains_key(&entity) {
+            ids.insert(entity, ids.len() as u64);
+            saved.push(entity);
+        }
+    }
+
+    let serialize_error = |message: String| Error::Serialize {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let saved = with_saved_ids(ids.clone(), || {
+        saved
+            .iter()
+            .map(|entity| {
+                let mut components = BTreeMap::new();
+                for (name, registration) in registry.serializable() {
+                    if let Some(value) = (registration.serialize)(world, *entity) {
+                        let value = value.map_err(|error| {
+                            serialize_error(format!("component `{}`: {}", name, error))
+                        })?;
+                        components.insert(name.to_string(), value);
+                    }
+                }
+
+                Ok(SavedEntity {
+                    id: ids[entity],
+                    components,
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let contents = format.serialize(path, &SavedWorld { entities: saved })?;
+
+    fs::write(path, contents).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Creates the entities saved in `path` in `world`, remapping the references between them.
+///
+/// Returns the created entities in the order they appear in the file. On error, nothing is
+/// added to `world`.
+pub fn load<P>(registry: &ComponentRegistry, world: &mut World, path: P) -> Result<Vec<Entity>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let format = format_of(path)?;
+
+    let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let saved = format.parse::<SavedWorld>(path, &contents)?;
+
+    let entities = saved
+        .entities
+        .into_iter()
+        .map(|saved_entity| (Some(saved_entity.id), saved_entity.components))
+        .collect();
+
+    spawn(registry, world, entities).map_err(|message| Error::Parse {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        message,
+    })
+}
+
+/// Creates one entity per element of `entities` with the components deserialized from the
+/// values under their registered names. References to the given ids are remapped to the
+/// created entities.
+///
+/// On error, nothing is added to `world` and a message naming the faulty entity is returned.
+pub(crate) fn spawn(
+    registry: &ComponentRegistry,
+    world: &mut World,
+    entities: Vec<(Option<u64>, BTreeMap<String, Value>)>,
+) -> std::result::Result<Vec<Entity>, String> {
+    // Entities are created up front, so that components can refer to entities appearing
+    // later on.
+    let mut ids = HashMap::with_capacity(entities.len());
+    let mut created = Vec::with_capacity(entities.len());
+    for (id, _) in &entities {
+        let entity = world.insert((), vec![()])[0];
+        created.push(entity);
+
+        if let Some(id) = *id {
+            if ids.insert(id, entity).is_some() {
+                delete_all(world, &created);
+                return Err(format!("duplicate entity id {}", id));
+            }
+        }
+    }
+
+    let components = with_loaded_entities(ids, || {
+        entities
+            .into_iter()
+            .zip(created.iter().copied())
+            .flat_map(|((id, components), entity)| {
+                let label = id.map_or_else(|| "entity".to_string(), |id| format!("entity {}", id));
+                components
+                    .into_iter()
+                    .map(move |(name, value)| (label.clone(), entity, name, value))
+            })
+            .map(|(label, entity, name, value)| {
+                let registration = registry
+                    .serializable_named(&name)
+                    .ok_or_else(|| format!("{}: unknown component `{}`", label, name))?;
+                let component = (registration.deserialize)(value)
+                    .map_err(|error| format!("{}: component `{}`: {}", label, name, error))?;
+                Ok((entity, registration.add, component))
+            })
+            .collect::<std::result::Result<Vec<(Entity, AddComponent, BoxedComponent)>, String>>()
+    });
+
+    match components {
+        Ok(components) => {
+            for (entity, add, component) in components {
+                add(world, entity, component);
+            }
+            Ok(created)
+        }
+        Err(error) => {
+            delete_all(world, &created);
+            Err(error)
+        }
+    }
+}
+
+type AddComponent = fn(&mut World, Entity, BoxedComponent);
+
+/// Hashes the `components` of every entity of `world` that has at least one serializable
+/// component, by their serialized value, so that equal world states hash the same.
+///
+/// Entities without any of the named components, and names that are not registered, do not
+/// change the checksum.
+pub fn checksum(registry: &ComponentRegistry, world: &World, components: &[String]) -> u32 {
+    let entities = registry.serializable_entities(world);
+    let ids = entities
+        .iter()
+        .enumerate()
+        .map(|(id, entity)| (*entity, id as u64))
+        .collect::<HashMap<_, _>>();
+
+    let mut bytes = Vec::new();
+    with_saved_ids(ids.clone(), || {
+        for name in components {
+            let registration = match registry.serializable_named(name) {
+                Some(registration) => registration,
+                None => continue,
+            };
+            let mut values = (registration.entities)(world)
+                .into_iter()
+                .filter_map(|entity| {
+                    let value = (registration.serialize)(world, entity)?.ok()?;
+                    Some((ids[&entity], serde_yaml::to_string(&value).ok()?))
+                })
+                .collect::<Vec<_>>();
+            values.sort_by_key(|(id, _)| *id);
+
+            for (id, value) in values {
+                bytes.extend_from_slice(name.as_bytes());
+                bytes.extend_from_slice(&id.to_le_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+            }
+        }
+    });
+
+    crc32(&bytes)
+}
+
+pub(crate) fn format_of(path: &Path) -> Result<Format> {
+    Format::from_path(path).ok_or_else(|| Error::Parse {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        message: "unsupported file extension".to_string(),
+    })
+}
+
+pub(crate) fn delete_all(world: &mut World, entities: &[Entity]) {
+    for entity in entities {
+        world.delete(*entity);
+    }
+}
+
+fn with_saved_ids<R>(ids: HashMap<Entity, u64>, f: impl FnOnce() -> R) -> R {
+    SAVED_IDS.with(|saved| *saved.borrow_mut() = Some(ids));
+    let result = f();
+    SAVED_IDS.with(|saved| *saved.borrow_mut() = None);
+    result
+}
+
+fn with_loaded_entities<R>(entities: HashMap<u64, Entity>, f: impl FnOnce() -> R) -> R {
+    let previous = LOADED_ENTITIES.with(|loaded| loaded.replace(Some(entities)));
+    let result = f();
+    LOADED_ENTITIES.with(|loaded| *loaded.borrow_mut() = previous);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::query::{IntoQuery, Read};
+    use crate::ecs::world::Universe;
+    use std::path::PathBuf;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    enum Team {
+        Red,
+        Blue(u32),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Follow(#[serde(with = "entity")] Entity);
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry
+            .register_serializable::<Position>("position")
+            .unwrap();
+        registry.register_serializable::<Team>("team").unwrap();
+        registry.register_serializable::<Follow>("follow").unwrap();
+        registry
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("crius-{}-{}", std::process::id(), name))
+    }
+
+    fn round_trip(file_name: &str) {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let leader = world.insert((), vec![(Position { x: 1.0, y: 2.0 }, Team::Red)])[0];
+        let follower = world.insert((), vec![(Follow(leader), Team::Blue(3))])[0];
+
+        let path = temp_file(file_name);
+        save(&registry(), &world, &path).unwrap();
+
+        let mut loaded_world = universe.create_world();
+        let loaded = load(&registry(), &mut loaded_world, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        let (loaded_follower, loaded_leader) = match loaded_world.get_component::<Follow>(loaded[0])
+        {
+            Some(_) => (loaded[0], loaded[1]),
+            None => (loaded[1], loaded[0]),
+        };
+
+        assert_eq!(
+            *loaded_world
+                .get_component::<Follow>(loaded_follower)
+                .unwrap(),
+            Follow(loaded_leader)
+        );
+        assert_eq!(
+            *loaded_world
+                .get_component::<Position>(loaded_leader)
+                .unwrap(),
+            *world.get_component::<Position>(leader).unwrap()
+        );
+        assert_eq!(
+            *loaded_world.get_component::<Team>(loaded_leader).unwrap(),
+            Team::Red
+        );
+        assert_eq!(
+            *loaded_world.get_component::<Team>(loaded_follower).unwrap(),
+            *world.get_component::<Team>(follower).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_yaml() {
+        round_trip("world.yml");
+    }
+
+    #[test]
+    fn round_trips_ron() {
+        round_trip("world.ron");
+    }
+
+    #[test]
+    fn rejects_references_to_unsaved_entities() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let leader = world.insert((), vec![(Team::Red,)])[0];
+        let follower = world.insert((), vec![(Follow(leader),)])[0];
+
+        let path = temp_file("partial.yml");
+        let error = save_entities(&registry(), &world, &[follower], &path).unwrap_err();
+
+        assert!(matches!(error, Error::Serialize { .. }));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn leaves_the_world_untouched_on_unknown_components() {
+        let path = temp_file("unknown.yml");
+        fs::write(
+            &path,
+            "entities:\n  - id: 0\n    components:\n      team: Red\n  - id: 1\n    components:\n      health: 3\n",
+        )
+        .unwrap();
+
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let error = load(&registry(), &mut world, &path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        match error {
+            Error::Parse { message, .. } => assert!(message.contains("health"), "{}", message),
+            error => panic!("unexpected error: {}", error),
+        }
+        assert_eq!(<Read<Team>>::query().iter_immutable(&world).count(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Frame timing information shared by scenes and systems.
+
+use std::time::Duration;
+
+/// Timing information about the current frame.
+///
+/// The application inserts a `Time` into `world.resources` and advances it at the
+/// start of every frame. Systems can read it with `read_resource::<Time>()` and
+/// scenes can get a copy through [`Context::time`](../scene/struct.Context.html#method.time).
+#[derive(Debug, Clone, Copy)]
+pub struct Time {
+    delta: Duration,
+    unscaled_delta: Duration,
+    elapsed: Duration,
+    unscaled_elapsed: Duration,
+    fixed_delta: Duration,
+    frame_number: u64,
+    time_scale: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new(Duration::default())
+    }
+}
+
+impl Time {
+    pub(crate) fn new(fixed_delta: Duration) -> Self {
+        Self {
+            delta: Duration::default(),
+            unscaled_delta: Duration::default(),
+            elapsed: Duration::default(),
+            unscaled_elapsed: Duration::default(),
+            fixed_delta,
+            frame_number: 0,
+            time_scale: 1.0,
+        }
+    }
+
+    /// Time elapsed since the previous frame, multiplied by the time scale.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// [`delta`](#method.delta) in seconds.
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Time elapsed since the previous frame, unaffected by the time scale.
+    pub fn unscaled_delta(&self) -> Duration {
+        self.unscaled_delta
+    }
+
+    /// [`unscaled_delta`](#method.unscaled_delta) in seconds.
+    pub fn unscaled_delta_seconds(&self) -> f32 {
+        self.unscaled_delta.as_secs_f32()
+    }
+
+    /// Scaled time elapsed since the application started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Time elapsed since the application started, unaffected by the time scale.
+    pub fn unscaled_elapsed(&self) -> Duration {
+        self.unscaled_elapsed
+    }
+
+    /// Duration of a single `Scene::fixed_update` step.
+    pub fn fixed_delta(&self) -> Duration {
+        self.fixed_delta
+    }
+
+    /// [`fixed_delta`](#method.fixed_delta) in seconds.
+    pub fn fixed_delta_seconds(&self) -> f32 {
+        self.fixed_delta.as_secs_f32()
+    }
+
+    /// Number of frames started since the application started, starting at 1 on the first frame.
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// The factor frame time is multiplied by before it reaches `delta`, `elapsed`
+    /// and the fixed update accumulator.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Sets the time scale. `0.0` pauses scaled time, values below `1.0` slow it down.
+    /// Negative values are treated as `0.0`, infinity as `f32::MAX` and NaN as `1.0`.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = if time_scale.is_nan() {
+            1.0
+        } else {
+            time_scale.clamp(0.0, f32::MAX)
+        };
+    }
+
+    pub(crate) fn advance(&mut self, unscaled_delta: Duration) {
+        self.unscaled_delta = unscaled_delta;
+        // Huge scales saturate rather than overflow.
+        self.delta =
+            Duration::try_from_secs_f64(unscaled_delta.as_secs_f64() * f64::from(self.time_scale))
+                .unwrap_or(Duration::MAX);
+        self.unscaled_elapsed = self.unscaled_elapsed.saturating_add(self.unscaled_delta);
+        self.elapsed = self.elapsed.saturating_add(self.delta);
+        self.frame_number += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_delta_and_elapsed() {
+        let mut time = Time::new(Duration::from_millis(10));
+
+        time.advance(Duration::from_millis(20));
+        time.set_time_scale(0.5);
+        time.advance(Duration::from_millis(20));
+
+        assert_eq!(time.frame_number(), 2);
+        assert_eq!(time.delta(), Duration::from_millis(10));
+        assert_eq!(time.unscaled_delta(), Duration::from_millis(20));
+        assert_eq!(time.elapsed(), Duration::from_millis(30));
+        assert_eq!(time.unscaled_elapsed(), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn zero_time_scale_pauses_scaled_time() {
+        let mut time = Time::default();
+        time.set_time_scale(-1.0);
+        time.advance(Duration::from_millis(16));
+
+        assert_eq!(time.time_scale(), 0.0);
+        assert_eq!(time.delta(), Duration::default());
+        assert_eq!(time.elapsed(), Duration::default());
+    }
+
+    #[test]
+    fn sanitizes_non_finite_time_scales() {
+        let mut time = Time::default();
+
+        time.set_time_scale(f32::NAN);
+        assert_eq!(time.time_scale(), 1.0);
+
+        time.set_time_scale(f32::INFINITY);
+        assert_eq!(time.time_scale(), f32::MAX);
+        time.advance(Duration::from_millis(16));
+        time.advance(Duration::from_millis(16));
+        assert_eq!(time.delta(), Duration::MAX);
+        assert_eq!(time.elapsed(), Duration::MAX);
+
+        time.set_time_scale(f32::NEG_INFINITY);
+        assert_eq!(time.time_scale(), 0.0);
+    }
+}
@@ -0,0 +1,91 @@
+//! Fixed timestep accumulator used to drive `Scene::fixed_update`.
+
+use std::time::Duration;
+
+/// Accumulates frame time and converts it into a whole number of fixed steps.
+///
+/// The application inserts a `FixedTimestep` into `world.resources`, so scenes and
+/// systems can read the step size and the interpolation alpha of the current frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+    alpha: f32,
+}
+
+impl FixedTimestep {
+    /// Creates a timestep that runs `step` sized updates, at most `max_steps` per frame.
+    pub fn new(step: Duration, max_steps: u32) -> Self {
+        Self {
+            step,
+            max_steps: max_steps.max(1),
+            accumulator: Duration::default(),
+            alpha: 0.0,
+        }
+    }
+
+    /// The duration of a single fixed step.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// The maximum number of fixed steps run in a single frame.
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    /// How far the current frame is between the last fixed step and the next one,
+    /// in the `[0, 1)` range. Used to interpolate state when drawing.
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Adds `frame_delta` to the accumulator and returns the number of fixed steps to run.
+    ///
+    /// Time exceeding `max_steps` worth of steps is dropped, so a slow frame cannot
+    /// cause an ever growing number of fixed updates on the frames that follow.
+    pub(crate) fn advance(&mut self, frame_delta: Duration) -> u32 {
+        if self.step == Duration::default() {
+            return 0;
+        }
+
+        self.accumulator = self
+            .accumulator
+            .saturating_add(frame_delta)
+            .min(self.step * self.max_steps);
+
+        let steps = (self.accumulator.as_nanos() / self.step.as_nanos()) as u32;
+        self.accumulator -= self.step * steps;
+        self.alpha = self.accumulator.as_secs_f32() / self.step.as_secs_f32();
+
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_partial_steps() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 5);
+
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(4)), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4);
+
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.7).abs() < 1e-4);
+    }
+
+    #[test]
+    fn clamps_long_frames() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10), 3);
+
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(Duration::from_millis(5)), 0);
+    }
+}
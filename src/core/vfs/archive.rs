@@ -0,0 +1,437 @@
+//! The crius archive format.
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! header  magic "CRIUSPAK", version: u32, entry count: u32, index length: u32
+//! index   per entry: name length: u32, name (UTF-8, `/` separated),
+//!         offset: u64, stored length: u64, length: u64, compression: u8, checksum: u32
+//!         index checksum: u32
+//! blobs   the stored bytes of every entry, at their offset from the start of the file
+//! ```
+//!
+//! Checksums are CRC-32 (IEEE). Entry checksums cover the uncompressed bytes.
+
+use super::lz;
+use crate::core::error::{Error, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 8] = b"CRIUSPAK";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 20;
+/// The length of an index entry with an empty name.
+const MIN_ENTRY_LEN: usize = 4 + 8 + 8 + 8 + 1 + 4;
+
+const STORED: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+#[derive(Debug, Clone)]
+struct ArchiveEntry {
+    offset: u64,
+    stored_len: u64,
+    len: u64,
+    compression: u8,
+    checksum: u32,
+}
+
+/// An archive opened for reading. Entries are read from the file on demand.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    file: Mutex<File>,
+    entries: HashMap<String, ArchiveEntry>,
+}
+
+impl Archive {
+    /// Opens the archive at `path` and reads its index.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let io_error = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+
+        let mut file = File::open(&path).map_err(io_error)?;
+        let file_len = file.metadata().map_err(io_error)?.len();
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header).map_err(io_error)?;
+
+        let mut reader = ByteReader::new(&header);
+        if reader.bytes(MAGIC.len()) != Some(&MAGIC[..]) {
+            return Err(corrupt(&path, "not a crius archive"));
+        }
+        match reader.u32() {
+            Some(VERSION) => {}
+            version => {
+                return Err(corrupt(
+                    &path,
+                    &format!("unsupported archive version {:?}", version),
+                ))
+            }
+        }
+        let count = reader.u32().unwrap_or_default() as usize;
+        let index_len = reader.u32().unwrap_or_default() as usize;
+
+        // Sizes come from the file, so check them before allocating anything.
+        if (HEADER_LEN + index_len + 4) as u64 > file_len
+            || count.saturating_mul(MIN_ENTRY_LEN) > index_len
+        {
+            return Err(corrupt(&path, "truncated archive index"));
+        }
+
+        // The index is followed by its checksum.
+        let mut index = vec![0; index_len + 4];
+        file.read_exact(&mut index).map_err(io_error)?;
+
+        let mut reader = ByteReader::new(&index);
+        let mut entries = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let (name, entry) =
+                read_entry(&mut reader).ok_or_else(|| corrupt(&path, "truncated archive index"))?;
+            if entry
+                .offset
+                .checked_add(entry.stored_len)
+                .is_none_or(|end| end > file_len)
+            {
+                return Err(corrupt(
+                    &path,
+                    &format!("entry `{}` extends past the end of the archive", name),
+                ));
+            }
+            entries.insert(name, entry);
+        }
+
+        if reader.position != index_len || reader.u32() != Some(crc32(&index[..index_len])) {
+            return Err(corrupt(&path, "archive index checksum mismatch"));
+        }
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// The names of the entries, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Reads, decompresses and verifies the entry named `name`.
+    /// Returns `None` if the archive has no such entry.
+    pub fn read(&self, name: &str) -> Option<Result<Vec<u8>>> {
+        let entry = self.entries.get(name)?;
+        Some(self.read_entry(name, entry))
+    }
+
+    fn read_entry(&self, name: &str, entry: &ArchiveEntry) -> Result<Vec<u8>> {
+        let io_error = |source| Error::Io {
+            path: self.path.clone(),
+            source,
+        };
+
+        // Entries were checked to lie within the file when the index was read.
+        let mut stored = vec![0; entry.stored_len as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(entry.offset)).map_err(io_error)?;
+            file.read_exact(&mut stored).map_err(io_error)?;
+        }
+
+        let data = match entry.compression {
+            STORED => Some(stored),
+            COMPRESSED => lz::decompress(&stored, entry.len as usize),
+            _ => None,
+        };
+
+        data.filter(|data| crc32(data) == entry.checksum)
+            .ok_or_else(|| corrupt(&self.path, &format!("entry `{}` is corrupted", name)))
+    }
+}
+
+/// Collects files and writes them into an archive.
+#[derive(Debug, Default)]
+pub struct ArchiveWriter {
+    // Ordered by name, so that archives of the same files are identical.
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl ArchiveWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file named `name`, replacing any file previously added under that name.
+    pub fn add<S>(&mut self, name: S, data: Vec<u8>)
+    where
+        S: Into<String>,
+    {
+        self.files.insert(name.into(), data);
+    }
+
+    /// Adds every file under `directory`, recursively, named by their path relative to it.
+    pub fn add_directory<P>(&mut self, directory: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        self.add_directory_files(directory, directory)
+    }
+
+    fn add_directory_files(&mut self, root: &Path, directory: &Path) -> Result<()> {
+        let io_error = |source| Error::Io {
+            path: directory.to_path_buf(),
+            source,
+        };
+
+        let mut entries = fs::read_dir(directory)
+            .map_err(io_error)?
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(io_error)?;
+        entries.sort_by_key(|entry| entry.path());
+
+        for entry in entries {
+            let path = entry.path();
+            if path.is_dir() {
+                self.add_directory_files(root, &path)?;
+            } else {
+                let data = fs::read(&path).map_err(|source| Error::Io {
+                    path: path.clone(),
+                    source,
+                })?;
+                let name = super::entry_name(path.strip_prefix(root).unwrap_or(&path))
+                    .ok_or_else(|| corrupt(&path, "file name is not valid UTF-8"))?;
+                self.add(name, data);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Writes the archive to `path`. Blobs are compressed when that makes them smaller.
+    pub fn write<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        let blobs = self
+            .files
+            .values()
+            .map(|data| {
+                let compressed = lz::compress(data);
+                if compressed.len() < data.len() {
+                    (COMPRESSED, compressed)
+                } else {
+                    (STORED, data.clone())
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let index_len = self
+            .files
+            .keys()
+            .map(|name| 4 + name.len() + 8 + 8 + 8 + 1 + 4)
+            .sum::<usize>();
+        let mut offset = (HEADER_LEN + index_len + 4) as u64;
+
+        let mut output = Vec::new();
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&VERSION.to_le_bytes());
+        output.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(index_len as u32).to_le_bytes());
+
+        for ((name, data), (compression, blob)) in self.files.iter().zip(&blobs) {
+            output.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            output.extend_from_slice(name.as_bytes());
+            output.extend_from_slice(&offset.to_le_bytes());
+            output.extend_from_slice(&(blob.len() as u64).to_le_bytes());
+            output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            output.push(*compression);
+            output.extend_from_slice(&crc32(data).to_le_bytes());
+            offset += blob.len() as u64;
+        }
+
+        let index_checksum = crc32(&output[HEADER_LEN..]);
+        output.extend_from_slice(&index_checksum.to_le_bytes());
+
+        for (_, blob) in &blobs {
+            output.extend_from_slice(blob);
+        }
+
+        fs::write(path, output).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+fn read_entry(reader: &mut ByteReader) -> Option<(String, ArchiveEntry)> {
+    let name_len = reader.u32()? as usize;
+    let name = String::from_utf8(reader.bytes(name_len)?.to_vec()).ok()?;
+    let entry = ArchiveEntry {
+        offset: reader.u64()?,
+        stored_len: reader.u64()?,
+        len: reader.u64()?,
+        compression: reader.bytes(1)?[0],
+        checksum: reader.u32()?,
+    };
+    Some((name, entry))
+}
+
+fn corrupt(path: &Path, message: &str) -> Error {
+    Error::Parse {
+        path: path.to_path_buf(),
+        line: None,
+        column: None,
+        message: message.to_string(),
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Some(u64::from_le_bytes(bytes))
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_standard_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trips_and_detects_corruption() {
+        let path = std::env::temp_dir().join(format!("crius-archive-{}.pak", std::process::id()));
+
+        let mut writer = ArchiveWriter::new();
+        writer.add("text/hello.txt", b"hello hello hello hello".to_vec());
+        writer.add("empty", Vec::new());
+        writer.write(&path).unwrap();
+
+        let archive = Archive::open(&path).unwrap();
+        assert_eq!(
+            archive.read("text/hello.txt").unwrap().unwrap(),
+            b"hello hello hello hello"
+        );
+        assert_eq!(archive.read("empty").unwrap().unwrap(), b"");
+        assert!(archive.read("missing").is_none());
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        let archive = Archive::open(&path).unwrap();
+        let result = archive.read("text/hello.txt").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn rejects_sizes_beyond_the_end_of_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("crius-archive-sizes-{}.pak", std::process::id()));
+
+        let mut writer = ArchiveWriter::new();
+        writer.add("hello.txt", b"hello".to_vec());
+        writer.write(&path).unwrap();
+        let valid = fs::read(&path).unwrap();
+
+        // A huge index length in the header.
+        let mut huge_index = valid.clone();
+        huge_index[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        // A huge stored length for the only entry, after its name.
+        let mut huge_entry = valid.clone();
+        let stored_len = HEADER_LEN + 4 + "hello.txt".len() + 8;
+        huge_entry[stored_len..stored_len + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        // Cut in the middle of the blob.
+        let truncated = valid[..valid.len() - 1].to_vec();
+
+        for bytes in [huge_index, huge_entry, truncated] {
+            fs::write(&path, bytes).unwrap();
+            assert!(matches!(Archive::open(&path), Err(Error::Parse { .. })));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
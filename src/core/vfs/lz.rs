@@ -0,0 +1,159 @@
+//! A small LZ77 codec for archive blobs.
+//!
+//! The compressed stream is a sequence of tokens, each starting with a varint `header`:
+//! - `header & 1 == 0`: a run of `header >> 1` literal bytes follows.
+//! - `header & 1 == 1`: a match of `(header >> 1) + MIN_MATCH` bytes, copied from the varint
+//!   offset that follows, counted back from the end of the output.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 1 << 16;
+/// The most output reserved up front, since `expected_len` comes from untrusted headers.
+const MAX_RESERVED: usize = 1 << 20;
+
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2);
+    let mut recent = HashMap::<[u8; MIN_MATCH], usize>::new();
+    let mut literals_start = 0;
+    let mut position = 0;
+
+    while position + MIN_MATCH <= input.len() {
+        let key = [
+            input[position],
+            input[position + 1],
+            input[position + 2],
+            input[position + 3],
+        ];
+
+        let candidate = match recent.insert(key, position) {
+            Some(candidate) if position - candidate <= MAX_OFFSET => candidate,
+            _ => {
+                position += 1;
+                continue;
+            }
+        };
+
+        let length = input[position..]
+            .iter()
+            .zip(&input[candidate..])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if length < MIN_MATCH {
+            position += 1;
+            continue;
+        }
+
+        write_literals(&mut output, &input[literals_start..position]);
+        write_varint(&mut output, (((length - MIN_MATCH) as u64) << 1) | 1);
+        write_varint(&mut output, (position - candidate) as u64);
+
+        position += length;
+        literals_start = position;
+    }
+
+    write_literals(&mut output, &input[literals_start..]);
+    output
+}
+
+/// Decompresses `input` into `expected_len` bytes. Returns `None` for malformed input.
+pub(crate) fn decompress(input: &[u8], expected_len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(expected_len.min(MAX_RESERVED));
+    let mut position = 0;
+
+    while position < input.len() {
+        let header = read_varint(input, &mut position)?;
+        let length = usize::try_from(header >> 1).ok()?;
+
+        if header & 1 == 0 {
+            let literals = input.get(position..position.checked_add(length)?)?;
+            output.extend_from_slice(literals);
+            position += length;
+        } else {
+            let offset = usize::try_from(read_varint(input, &mut position)?).ok()?;
+            let length = length.checked_add(MIN_MATCH)?;
+            if offset == 0 || offset > output.len() || output.len() + length > expected_len {
+                return None;
+            }
+
+            // Matches may overlap the bytes they produce, so copy one byte at a time.
+            let start = output.len() - offset;
+            for index in 0..length {
+                output.push(output[start + index]);
+            }
+        }
+
+        if output.len() > expected_len {
+            return None;
+        }
+    }
+
+    Some(output).filter(|output| output.len() == expected_len)
+}
+
+fn write_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    if !literals.is_empty() {
+        write_varint(output, (literals.len() as u64) << 1);
+        output.extend_from_slice(literals);
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *input.get(*position)?;
+        *position += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_repetitive_and_random_data() {
+        let repetitive = b"abcabcabcabcabcabcabcabc hello hello hello".repeat(50);
+        let compressed = compress(&repetitive);
+        assert!(compressed.len() < repetitive.len() / 4);
+        assert_eq!(
+            decompress(&compressed, repetitive.len()).unwrap(),
+            repetitive
+        );
+
+        let mut state = 0x2545_f491_u32;
+        let noise = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(decompress(&compress(&noise), noise.len()).unwrap(), noise);
+
+        assert_eq!(decompress(&compress(&[]), 0).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        // A match reaching before the start of the output.
+        assert!(decompress(&[0x01, 0x05], 8).is_none());
+        // A literal run longer than the input.
+        assert!(decompress(&[0x10, b'a'], 8).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+//! A virtual file system over archives and loose directories.
+//!
+//! The application inserts a [`Vfs`](struct.Vfs.html) rooted at `Settings::assets_path`
+//! into `world.resources`, with the assets directory mounted at priority 0 and the mounts
+//! listed in `Settings::mounts` on top of it. Files are read from the mount with the highest
+//! priority that has them, so that mods and patches can override shipped files.
+//!
+//! Archives are written with [`ArchiveWriter`](struct.ArchiveWriter.html), or with the
+//! `crius-pack` binary.
+
+mod archive;
+mod lz;
+
+pub use self::archive::{Archive, ArchiveWriter};
+
+pub(crate) use self::archive::crc32;
+
+use crate::core::error::{Error, Result};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+enum MountSource {
+    Directory(PathBuf),
+    Archive(Archive),
+}
+
+struct Mount {
+    priority: i32,
+    source: MountSource,
+}
+
+/// Reads files relative to the assets directory from the mounted archives and directories.
+///
+/// Clones share their mounts, so a file system handed to background threads sees the
+/// mounts added afterwards.
+#[derive(Clone)]
+pub struct Vfs {
+    root: PathBuf,
+    // Ordered by ascending priority, then by the order they were mounted in.
+    mounts: Arc<RwLock<Vec<Mount>>>,
+}
+
+impl Vfs {
+    /// Creates a file system with the `root` directory mounted at priority 0.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        let root = root.into();
+        let vfs = Self {
+            root: root.clone(),
+            mounts: Arc::new(RwLock::new(Vec::new())),
+        };
+        vfs.insert(0, MountSource::Directory(root));
+        vfs
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Mounts the archive or directory at `path`, relative to the root, with `priority`.
+    /// Among mounts of the same priority, the last one mounted wins.
+    pub fn mount<P>(&self, path: P, priority: i32) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.root.join(path);
+        let metadata = fs::metadata(&path).map_err(|source| Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+
+        let source = if metadata.is_dir() {
+            MountSource::Directory(path)
+        } else {
+            MountSource::Archive(Archive::open(path)?)
+        };
+
+        self.insert(priority, source);
+        Ok(())
+    }
+
+    fn insert(&self, priority: i32, source: MountSource) {
+        let mut mounts = self.mounts.write().unwrap();
+        let index = mounts
+            .iter()
+            .position(|mount| mount.priority > priority)
+            .unwrap_or(mounts.len());
+        mounts.insert(index, Mount { priority, source });
+    }
+
+    /// Whether a mount has a file at `path`.
+    pub fn exists<P>(&self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let name = match entry_name(path.as_ref()) {
+            Some(name) => name,
+            None => return false,
+        };
+
+        self.mounts
+            .read()
+            .unwrap()
+            .iter()
+            .any(|mount| match &mount.source {
+                MountSource::Directory(directory) => directory.join(&name).is_file(),
+                MountSource::Archive(archive) => archive.contains(&name),
+            })
+    }
+
+    /// Reads the file at `path` from the mount with the highest priority that has it.
+    pub fn read<P>(&self, path: P) -> Result<Vec<u8>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let not_found = || Error::Io {
+            path: self.root.join(path),
+            source: io::Error::new(io::ErrorKind::NotFound, "no mount has this file"),
+        };
+
+        let name = entry_name(path).ok_or_else(not_found)?;
+        for mount in self.mounts.read().unwrap().iter().rev() {
+            match &mount.source {
+                MountSource::Directory(directory) => {
+                    let path = directory.join(&name);
+                    match fs::read(&path) {
+                        Ok(data) => return Ok(data),
+                        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                        Err(source) => return Err(Error::Io { path, source }),
+                    }
+                }
+                MountSource::Archive(archive) => {
+                    if let Some(data) = archive.read(&name) {
+                        return data;
+                    }
+                }
+            }
+        }
+
+        Err(not_found())
+    }
+
+    /// Reads the file at `path` as UTF-8 text.
+    pub fn read_to_string<P>(&self, path: P) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        String::from_utf8(self.read(path)?).map_err(|error| Error::Parse {
+            path: self.root.join(path),
+            line: None,
+            column: None,
+            message: error.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for Vfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vfs")
+            .field("root", &self.root)
+            .field("mounts", &self.mounts.read().unwrap().len())
+            .finish()
+    }
+}
+
+/// The `/` separated name of a relative `path`, or `None` if it leaves the root.
+fn entry_name(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_from_the_highest_priority_mount() {
+        let root = std::env::temp_dir().join(format!("crius-vfs-{}", std::process::id()));
+        fs::create_dir_all(root.join("patch/text")).unwrap();
+        fs::write(root.join("base.txt"), "loose base").unwrap();
+        fs::write(root.join("shadowed.txt"), "loose").unwrap();
+        fs::write(root.join("patch/text/patched.txt"), "patch").unwrap();
+
+        let mut writer = ArchiveWriter::new();
+        writer.add("shadowed.txt", b"archive".to_vec());
+        writer.add("text/patched.txt", b"archive".to_vec());
+        writer.write(root.join("content.pak")).unwrap();
+
+        let vfs = Vfs::new(&root);
+        vfs.mount("content.pak", 1).unwrap();
+        vfs.mount("patch", 2).unwrap();
+
+        let read = |path: &str| vfs.read_to_string(path).unwrap();
+        assert_eq!(read("base.txt"), "loose base");
+        assert_eq!(read("shadowed.txt"), "archive");
+        assert_eq!(read("./text/patched.txt"), "patch");
+        assert!(vfs.exists("text/patched.txt"));
+        assert!(!vfs.exists("../base.txt"));
+        assert!(matches!(vfs.read("missing.txt"), Err(Error::Io { .. })));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
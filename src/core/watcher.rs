@@ -0,0 +1,176 @@
+//! Detecting changes to files by polling their metadata.
+//!
+//! The application watches its settings files and the assets directory. Changes to the
+//! settings files are applied to the running application, and changes under the assets
+//! directory are written to the `EventChannel<FileChanged>` resource, with paths relative
+//! to the assets directory. Settings files that fail to reload are reported to the
+//! `EventChannel<SettingsError>` resource, and the previous settings stay in use.
+
+use crate::core::error::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileChanged {
+    pub path: PathBuf,
+    pub kind: FileChangeKind,
+}
+
+/// The error that kept changed settings files from being reloaded.
+#[derive(Debug, Clone)]
+pub struct SettingsError {
+    pub error: Arc<Error>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Watches files and directories, recursively, for changes.
+///
+/// Paths that do not exist yet can be watched, their creation is reported as a change.
+pub struct FileWatcher {
+    roots: Vec<PathBuf>,
+    files: HashMap<PathBuf, FileStamp>,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(poll_interval: Duration) -> Self {
+        Self {
+            roots: Vec::new(),
+            files: HashMap::new(),
+            poll_interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch<P>(&mut self, path: P)
+    where
+        P: Into<PathBuf>,
+    {
+        let root = path.into();
+        scan(&root, &mut self.files);
+        self.roots.push(root);
+    }
+
+    /// Scans the watched paths if the poll interval has elapsed since the last scan.
+    /// Returns the changes found, ordered by path.
+    pub fn poll(&mut self) -> Vec<FileChanged> {
+        if self.last_poll.elapsed() < self.poll_interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut files = HashMap::with_capacity(self.files.len());
+        for root in &self.roots {
+            scan(root, &mut files);
+        }
+
+        let mut changes = files
+            .iter()
+            .filter_map(|(path, stamp)| {
+                let kind = match self.files.get(path) {
+                    None => FileChangeKind::Created,
+                    Some(previous) if previous != stamp => FileChangeKind::Modified,
+                    Some(_) => return None,
+                };
+                Some(FileChanged {
+                    path: path.clone(),
+                    kind,
+                })
+            })
+            .chain(
+                self.files
+                    .keys()
+                    .filter(|path| !files.contains_key(*path))
+                    .map(|path| FileChanged {
+                        path: path.clone(),
+                        kind: FileChangeKind::Removed,
+                    }),
+            )
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        self.files = files;
+        changes
+    }
+}
+
+fn scan(path: &Path, files: &mut HashMap<PathBuf, FileStamp>) {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return,
+    };
+
+    if metadata.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                scan(&entry.path(), files);
+            }
+        }
+    } else {
+        files.insert(
+            path.to_path_buf(),
+            FileStamp {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_created_modified_and_removed_files() {
+        let root = std::env::temp_dir().join(format!("crius-watcher-{}", std::process::id()));
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("kept.txt"), "a").unwrap();
+        fs::write(root.join("removed.txt"), "a").unwrap();
+
+        let mut watcher = FileWatcher::new(Duration::from_secs(0));
+        watcher.watch(&root);
+        assert!(watcher.poll().is_empty());
+
+        fs::write(root.join("kept.txt"), "ab").unwrap();
+        fs::write(root.join("nested/created.txt"), "a").unwrap();
+        fs::remove_file(root.join("removed.txt")).unwrap();
+
+        let changes = watcher.poll();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                FileChanged {
+                    path: root.join("kept.txt"),
+                    kind: FileChangeKind::Modified,
+                },
+                FileChanged {
+                    path: root.join("nested/created.txt"),
+                    kind: FileChangeKind::Created,
+                },
+                FileChanged {
+                    path: root.join("removed.txt"),
+                    kind: FileChangeKind::Removed,
+                },
+            ]
+        );
+    }
+}
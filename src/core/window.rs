@@ -0,0 +1,498 @@
+//! The application's windows, and controlling them from scenes and systems.
+//!
+//! The windows are kept in the [`Windows`](struct.Windows.html) resource, keyed by the
+//! `WindowId` that `Event::Window` carries. Changes are queued in the
+//! [`WindowCommands`](struct.WindowCommands.html) resource and applied by the event loop once
+//! the current callback returns. Applications without a window drop them. Commands that
+//! fail are written to the `EventChannel<WindowError>` resource, and the fallbacks taken for
+//! fullscreen settings that cannot be followed are kept by `Windows`.
+
+use crate::core::application::settings::{
+    FullscreenMode, FullscreenSettings, MonitorSelector, VideoModeSettings, WindowSettings,
+};
+use crate::core::error::Result;
+use crate::core::event::UserEvent;
+use legion::resource::Resources;
+use std::collections::HashMap;
+use winit::dpi::{LogicalPosition, PhysicalSize};
+use winit::event_loop::EventLoopWindowTarget;
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+/// Identifies a window for as long as the application runs. Ids are handed out in the
+/// order the windows open and are not reused, so replays can give the recorded windows
+/// the same ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowId(u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowCommand {
+    /// Opens a window described by the settings. Its name must not be in use.
+    Open(WindowSettings),
+    /// Closes a window. Closing the primary window exits the application.
+    Close(WindowId),
+    /// Confines the cursor to a window, or releases it.
+    SetCursorGrab(WindowId, bool),
+    SetCursorVisible(WindowId, bool),
+    SetFullscreen(WindowId, FullscreenSettings),
+    /// Moves the top-left corner of a window to a position on the desktop.
+    SetPosition(WindowId, LogicalPosition<i32>),
+}
+
+/// The window changes requested since the event loop last applied them.
+#[derive(Debug, Default)]
+pub struct WindowCommands {
+    commands: Vec<WindowCommand>,
+}
+
+impl WindowCommands {
+    pub fn push(&mut self, command: WindowCommand) {
+        self.commands.push(command);
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<WindowCommand> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+/// A fullscreen setting that could not be followed, and what is used instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenFallback {
+    /// The preferred monitor is not connected, so the primary monitor is used.
+    PrimaryMonitor,
+    /// The monitor has no video mode, so borderless fullscreen is used.
+    Borderless,
+}
+
+/// A window command that could not be carried out.
+#[derive(Debug, Clone)]
+pub struct WindowError {
+    pub command: WindowCommand,
+    pub message: String,
+}
+
+struct NamedWindow {
+    name: String,
+    /// `None` for the windows of a recording, which replays do not open.
+    window: Option<Window>,
+    fullscreen_fallbacks: Vec<FullscreenFallback>,
+}
+
+/// The open windows of the application.
+#[derive(Default)]
+pub struct Windows {
+    windows: HashMap<WindowId, NamedWindow>,
+    winit_ids: HashMap<winit::window::WindowId, WindowId>,
+    primary: Option<WindowId>,
+    next_id: u32,
+}
+
+impl Windows {
+    pub fn get(&self, id: WindowId) -> Option<&Window> {
+        self.windows
+            .get(&id)
+            .and_then(|named| named.window.as_ref())
+    }
+
+    pub fn named(&self, name: &str) -> Option<&Window> {
+        self.id_of(name).and_then(|id| self.get(id))
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<WindowId> {
+        self.windows
+            .iter()
+            .find(|(_, named)| named.name == name)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn name_of(&self, id: WindowId) -> Option<&str> {
+        self.windows.get(&id).map(|named| named.name.as_str())
+    }
+
+    /// The id of the window opened first, which closes the application when it closes.
+    pub fn primary_id(&self) -> Option<WindowId> {
+        self.primary
+    }
+
+    pub fn primary(&self) -> Option<&Window> {
+        self.primary.and_then(|id| self.get(id))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.windows.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// The fallbacks taken when the window last changed its fullscreen settings, empty if
+    /// they were followed.
+    pub fn fullscreen_fallbacks(&self, id: WindowId) -> &[FullscreenFallback] {
+        self.windows
+            .get(&id)
+            .map_or(&[], |named| named.fullscreen_fallbacks.as_slice())
+    }
+
+    /// Applies `settings` to the window `id`, if it is open.
+    pub(crate) fn set_fullscreen(&mut self, id: WindowId, settings: &FullscreenSettings) {
+        if let Some(NamedWindow {
+            window: Some(window),
+            fullscreen_fallbacks,
+            ..
+        }) = self.windows.get_mut(&id)
+        {
+            *fullscreen_fallbacks = set_fullscreen(window, settings);
+        }
+    }
+
+    /// The id of the window winit knows as `id`.
+    pub(crate) fn id_of_winit(&self, id: winit::window::WindowId) -> Option<WindowId> {
+        self.winit_ids.get(&id).copied()
+    }
+
+    /// Adds a window built by `build_window`.
+    pub(crate) fn insert(
+        &mut self,
+        name: String,
+        (window, fullscreen_fallbacks): (Window, Vec<FullscreenFallback>),
+    ) -> WindowId {
+        let winit_id = window.id();
+        let id = self.add(name, Some(window), fullscreen_fallbacks);
+        self.winit_ids.insert(winit_id, id);
+        id
+    }
+
+    /// Adds a window that is not open, standing in for a window of a recording.
+    pub(crate) fn insert_placeholder(&mut self, name: String) -> WindowId {
+        self.add(name, None, Vec::new())
+    }
+
+    /// Removes the window, which closes it once dropped.
+    pub(crate) fn remove(&mut self, id: WindowId) -> Option<Window> {
+        let window = self.windows.remove(&id)?.window?;
+        self.winit_ids.remove(&window.id());
+        Some(window)
+    }
+
+    fn add(
+        &mut self,
+        name: String,
+        window: Option<Window>,
+        fullscreen_fallbacks: Vec<FullscreenFallback>,
+    ) -> WindowId {
+        let id = WindowId(self.next_id);
+        self.next_id += 1;
+        self.primary.get_or_insert(id);
+        self.windows.insert(
+            id,
+            NamedWindow {
+                name,
+                window,
+                fullscreen_fallbacks,
+            },
+        );
+        id
+    }
+}
+
+/// Opens a window described by `settings`. Also returns the fallbacks taken to make it
+/// fullscreen.
+pub(crate) fn build_window(
+    settings: &WindowSettings,
+    target: &EventLoopWindowTarget<UserEvent>,
+) -> Result<(Window, Vec<FullscreenFallback>)> {
+    let mut window_builder = WindowBuilder::new()
+        .with_title(settings.title.clone())
+        .with_resizable(settings.resizeable)
+        .with_maximized(settings.maximized)
+        // Shown once placed, so that it does not jump to its position.
+        .with_visible(false)
+        .with_transparent(settings.transparent)
+        .with_decorations(settings.decorations)
+        .with_always_on_top(settings.always_on_top);
+
+    if let Some(size) = settings.size {
+        window_builder = window_builder.with_inner_size(size);
+    }
+
+    if let Some(min) = settings.min_size {
+        window_builder = window_builder.with_min_inner_size(min);
+    }
+
+    if let Some(max) = settings.max_size {
+        window_builder = window_builder.with_max_inner_size(max);
+    }
+
+    let window = window_builder.build(target)?;
+    if let Some(position) = settings.position {
+        window.set_outer_position(position);
+    }
+    let fullscreen_fallbacks = if settings.fullscreen.mode != FullscreenMode::Windowed {
+        set_fullscreen(&window, &settings.fullscreen)
+    } else {
+        Vec::new()
+    };
+    window.set_visible(settings.visible);
+    Ok((window, fullscreen_fallbacks))
+}
+
+/// Applies `settings` to `window`, falling back to the primary monitor when the preferred
+/// one is not connected and to borderless fullscreen when no video mode is available.
+/// Returns the fallbacks taken.
+fn set_fullscreen(window: &Window, settings: &FullscreenSettings) -> Vec<FullscreenFallback> {
+    let mut fallbacks = Vec::new();
+    let mut pick_monitor = || {
+        let (monitor, fallback) = monitor(window, settings);
+        fallbacks.extend(fallback);
+        monitor
+    };
+    let fullscreen = match settings.mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless => Some(Fullscreen::Borderless(pick_monitor())),
+        FullscreenMode::Exclusive => {
+            let monitor = pick_monitor();
+            let modes = monitor.video_modes().collect::<Vec<_>>();
+            let summaries = modes
+                .iter()
+                .map(|mode| (mode.size(), mode.refresh_rate(), mode.bit_depth()))
+                .collect::<Vec<_>>();
+            match find_video_mode(&summaries, settings.video_mode.as_ref()) {
+                Some(index) => Some(Fullscreen::Exclusive(modes[index].clone())),
+                None => {
+                    fallbacks.push(FullscreenFallback::Borderless);
+                    Some(Fullscreen::Borderless(monitor))
+                }
+            }
+        }
+    };
+    window.set_fullscreen(fullscreen);
+    fallbacks
+}
+
+/// The monitor `settings` prefers, or the primary monitor along with the fallback.
+fn monitor(
+    window: &Window,
+    settings: &FullscreenSettings,
+) -> (MonitorHandle, Option<FullscreenFallback>) {
+    let selector = match &settings.monitor {
+        Some(selector) => selector,
+        None => return (window.current_monitor(), None),
+    };
+
+    let monitors = window.available_monitors().collect::<Vec<_>>();
+    let names = monitors.iter().map(MonitorHandle::name).collect::<Vec<_>>();
+    match find_monitor(&names, selector) {
+        Some(index) => (monitors[index].clone(), None),
+        None => (
+            window.primary_monitor(),
+            Some(FullscreenFallback::PrimaryMonitor),
+        ),
+    }
+}
+
+/// The index of the monitor `selector` refers to among monitors named `names`.
+fn find_monitor(names: &[Option<String>], selector: &MonitorSelector) -> Option<usize> {
+    match selector {
+        MonitorSelector::Index(index) => Some(*index).filter(|index| *index < names.len()),
+        MonitorSelector::Name(name) => names
+            .iter()
+            .position(|candidate| candidate.as_deref() == Some(name.as_str())),
+    }
+}
+
+/// The index of the video mode closest to `preferred` among `modes`, given as size, refresh
+/// rate and bit depth. Without a preference, the largest mode wins. Ties go to the
+/// preferred refresh rate and bit depth, then to the highest ones.
+fn find_video_mode(
+    modes: &[(PhysicalSize<u32>, u16, u16)],
+    preferred: Option<&VideoModeSettings>,
+) -> Option<usize> {
+    let size_score = |size: PhysicalSize<u32>| {
+        let (width, height) = (i64::from(size.width), i64::from(size.height));
+        match preferred {
+            Some(preferred) => {
+                -(width - i64::from(preferred.size.width)).abs()
+                    - (height - i64::from(preferred.size.height)).abs()
+            }
+            None => width * height,
+        }
+    };
+    let matches = |wanted: Option<u16>, actual: u16| wanted.is_some() && wanted == Some(actual);
+
+    (0..modes.len()).max_by_key(|&index| {
+        let (size, refresh_rate, bit_depth) = modes[index];
+        (
+            size_score(size),
+            matches(preferred.and_then(|mode| mode.refresh_rate), refresh_rate),
+            matches(preferred.and_then(|mode| mode.bit_depth), bit_depth),
+            refresh_rate,
+            bit_depth,
+        )
+    })
+}
+
+/// Queues changes to a window. Returned by [`Context::window`] and the methods next to it.
+///
+/// [`Context::window`]: ../scene/struct.Context.html#method.window
+pub struct WindowControl<'a> {
+    resources: &'a Resources,
+    id: Option<WindowId>,
+}
+
+impl<'a> WindowControl<'a> {
+    /// Controls the window `id`, or nothing when it is `None`.
+    pub(crate) fn new(resources: &'a Resources, id: Option<WindowId>) -> Self {
+        Self { resources, id }
+    }
+
+    /// The id of the window, or `None` when there is no such window.
+    pub fn id(&self) -> Option<WindowId> {
+        self.id
+    }
+
+    /// Confines the cursor to the window, for example for first-person cameras that read
+    /// raw mouse motion from `Event::Device`.
+    pub fn set_cursor_grab(&self, grab: bool) {
+        self.push(|id| WindowCommand::SetCursorGrab(id, grab));
+    }
+
+    pub fn set_cursor_visible(&self, visible: bool) {
+        self.push(|id| WindowCommand::SetCursorVisible(id, visible));
+    }
+
+    /// Switches the window between windowed, borderless and exclusive fullscreen.
+    pub fn set_fullscreen(&self, settings: FullscreenSettings) {
+        self.push(|id| WindowCommand::SetFullscreen(id, settings));
+    }
+
+    pub fn set_windowed(&self) {
+        self.set_fullscreen(FullscreenSettings::default());
+    }
+
+    pub fn set_position(&self, position: LogicalPosition<i32>) {
+        self.push(|id| WindowCommand::SetPosition(id, position));
+    }
+
+    /// Closes the window. Closing the primary window exits the application.
+    pub fn close(&self) {
+        self.push(WindowCommand::Close);
+    }
+
+    fn push<F>(&self, command: F)
+    where
+        F: FnOnce(WindowId) -> WindowCommand,
+    {
+        if let (Some(id), Some(mut commands)) =
+            (self.id, self.resources.get_mut::<WindowCommands>())
+        {
+            commands.push(command(id));
+        }
+    }
+}
+
+/// Applies `command` to `windows`, except for `Open` which needs the event loop and `Close`
+/// which the application handles, since closing the primary window ends the run. Returns the
+/// error if it failed, since not every platform supports every change.
+pub(crate) fn apply(windows: &mut Windows, command: WindowCommand) -> Option<WindowError> {
+    match command {
+        WindowCommand::Open(_) | WindowCommand::Close(_) => {}
+        WindowCommand::SetCursorGrab(id, grab) => {
+            if let Some(window) = windows.get(id) {
+                if let Err(error) = window.set_cursor_grab(grab) {
+                    return Some(WindowError {
+                        command,
+                        message: format!("failed to set the cursor grab: {}", error),
+                    });
+                }
+            }
+        }
+        WindowCommand::SetCursorVisible(id, visible) => {
+            if let Some(window) = windows.get(id) {
+                window.set_cursor_visible(visible);
+            }
+        }
+        WindowCommand::SetFullscreen(id, settings) => windows.set_fullscreen(id, &settings),
+        WindowCommand::SetPosition(id, position) => {
+            if let Some(window) = windows.get(id) {
+                window.set_outer_position(position);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queues_commands_until_drained() {
+        let mut resources = Resources::default();
+        resources.insert(WindowCommands::default());
+        let id = Windows::default().insert_placeholder("main".to_string());
+
+        let window = WindowControl::new(&resources, Some(id));
+        window.set_cursor_grab(true);
+        window.set_cursor_visible(false);
+        window.close();
+        WindowControl::new(&resources, None).close();
+
+        let mut commands = resources.get_mut::<WindowCommands>().unwrap();
+        assert_eq!(
+            commands.drain(),
+            vec![
+                WindowCommand::SetCursorGrab(id, true),
+                WindowCommand::SetCursorVisible(id, false),
+                WindowCommand::Close(id)
+            ]
+        );
+        assert!(commands.drain().is_empty());
+    }
+
+    #[test]
+    fn finds_monitors_by_index_or_name() {
+        let names = vec![Some("DP-1".to_string()), None, Some("HDMI-1".to_string())];
+
+        assert_eq!(find_monitor(&names, &MonitorSelector::Index(1)), Some(1));
+        assert_eq!(find_monitor(&names, &MonitorSelector::Index(3)), None);
+        let hdmi = MonitorSelector::Name("HDMI-1".to_string());
+        assert_eq!(find_monitor(&names, &hdmi), Some(2));
+        let missing = MonitorSelector::Name("VGA-1".to_string());
+        assert_eq!(find_monitor(&names, &missing), None);
+    }
+
+    #[test]
+    fn picks_the_closest_video_mode() {
+        let modes = [
+            (PhysicalSize::new(1920, 1080), 60, 32),
+            (PhysicalSize::new(1920, 1080), 144, 32),
+            (PhysicalSize::new(2560, 1440), 60, 32),
+            (PhysicalSize::new(1280, 720), 60, 32),
+        ];
+        let preferred = |width, height, refresh_rate| VideoModeSettings {
+            size: PhysicalSize::new(width, height),
+            refresh_rate,
+            bit_depth: None,
+        };
+
+        assert_eq!(find_video_mode(&modes, None), Some(2));
+        assert_eq!(
+            find_video_mode(&modes, Some(&preferred(1920, 1080, None))),
+            Some(1)
+        );
+        assert_eq!(
+            find_video_mode(&modes, Some(&preferred(1920, 1080, Some(60)))),
+            Some(0)
+        );
+        assert_eq!(
+            find_video_mode(&modes, Some(&preferred(1366, 768, None))),
+            Some(3)
+        );
+        assert_eq!(find_video_mode(&[], None), None);
+    }
+}
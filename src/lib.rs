@@ -2,4 +2,8 @@ mod core;
 
 pub mod prelude;
 
-pub use crate::core::{application, ecs, event, scene};
+pub use crate::core::{
+    application, asset, ecs, error, event, input, pacing, plugin, prefab, registry, replay, scene,
+    schedule, serialization, time, timestep, vfs, watcher, window,
+};
+pub use crate::error::{Error, Result};
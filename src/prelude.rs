@@ -1,6 +1,10 @@
 pub use crate::{
     application::ApplicationBuilder,
     ecs::prelude::*,
-    scene::{Context, Scene, Transition},
     event::*,
+    input::{ActionState, InputState},
+    plugin::{Dependency, Plugin},
+    scene::{Context, Scene, Transition},
+    schedule::Stage,
+    time::Time,
 };
@@ -0,0 +1,1147 @@
+use crius::application::settings::{HeadlessSettings, Settings};
+use crius::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+fn unthrottled(max_frames: Option<u64>) -> HeadlessSettings {
+    HeadlessSettings {
+        tick_rate: None,
+        max_frames,
+    }
+}
+
+struct CountingScene {
+    updates: Arc<AtomicUsize>,
+}
+
+impl Scene for CountingScene {
+    fn update(&mut self, _context: Context) -> Transition {
+        self.updates.fetch_add(1, Ordering::SeqCst);
+        Transition::None
+    }
+}
+
+#[test]
+fn runs_until_max_frames() {
+    let updates = Arc::new(AtomicUsize::new(0));
+    let executions = Arc::new(AtomicUsize::new(0));
+    let system_executions = executions.clone();
+
+    ApplicationBuilder::new(
+        CountingScene {
+            updates: updates.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(10)))
+    .with_thread_local_fn(move |_| {
+        system_executions.fetch_add(1, Ordering::SeqCst);
+    })
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(updates.load(Ordering::SeqCst), 10);
+    assert_eq!(executions.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn stops_when_the_primary_window_is_closed() {
+    use crius::application::settings::WindowSettings;
+    use crius::window::Windows;
+
+    struct ClosingScene {
+        updates: Arc<AtomicUsize>,
+    }
+
+    impl Scene for ClosingScene {
+        fn update(&mut self, context: Context) -> Transition {
+            match self.updates.fetch_add(1, Ordering::SeqCst) {
+                0 => {
+                    let windows = context.world.resources.get::<Windows>().unwrap();
+                    let inspector = windows.id_of("inspector").unwrap();
+                    context.window_with_id(inspector).close();
+                }
+                2 => context.window().close(),
+                _ => {}
+            }
+            Transition::None
+        }
+    }
+
+    let updates = Arc::new(AtomicUsize::new(0));
+    ApplicationBuilder::new(
+        ClosingScene {
+            updates: updates.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings {
+        window: Some(WindowSettings::default()),
+        windows: vec![WindowSettings {
+            name: "inspector".to_string(),
+            ..WindowSettings::default()
+        }],
+        ..Settings::default()
+    })
+    .headless(unthrottled(Some(10)))
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(updates.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn runs_headless_when_settings_have_no_window() {
+    let updates = Arc::new(AtomicUsize::new(0));
+
+    struct QuittingScene {
+        updates: Arc<AtomicUsize>,
+    }
+
+    impl Scene for QuittingScene {
+        fn update(&mut self, _context: Context) -> Transition {
+            if self.updates.fetch_add(1, Ordering::SeqCst) == 2 {
+                Transition::Quit
+            } else {
+                Transition::None
+            }
+        }
+    }
+
+    ApplicationBuilder::new(
+        QuittingScene {
+            updates: updates.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(updates.load(Ordering::SeqCst), 3);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Marker(u32);
+
+struct GameplayScene {
+    log: Arc<Mutex<Vec<String>>>,
+    frames: u32,
+}
+
+impl Scene for GameplayScene {
+    fn start(&mut self, context: Context) {
+        self.log.lock().unwrap().push("gameplay::start".to_string());
+        context.world.insert((), vec![(Marker(0),)]);
+    }
+
+    fn stop(&mut self, context: Context) {
+        let markers = <Read<Marker>>::query()
+            .iter(context.world)
+            .map(|marker| *marker)
+            .collect::<Vec<_>>();
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("gameplay::stop {:?}", markers));
+    }
+
+    fn pause(&mut self, _context: Context) {
+        self.log.lock().unwrap().push("gameplay::pause".to_string());
+    }
+
+    fn resume(&mut self, _context: Context) {
+        self.log
+            .lock()
+            .unwrap()
+            .push("gameplay::resume".to_string());
+    }
+
+    fn update(&mut self, _context: Context) -> Transition {
+        self.frames += 1;
+        match self.frames {
+            2 => Transition::Push(Box::new(PauseScene {
+                log: self.log.clone(),
+            })),
+            4 => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+}
+
+struct PauseScene {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Scene for PauseScene {
+    fn start(&mut self, _context: Context) {
+        self.log.lock().unwrap().push("pause::start".to_string());
+    }
+
+    fn stop(&mut self, _context: Context) {
+        self.log.lock().unwrap().push("pause::stop".to_string());
+    }
+
+    fn update(&mut self, _context: Context) -> Transition {
+        Transition::Pop
+    }
+}
+
+#[test]
+fn drives_scene_transitions_and_systems() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    ApplicationBuilder::new(
+        GameplayScene {
+            log: log.clone(),
+            frames: 0,
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(5)))
+    .with_system("advance_markers", |_, system_builder| {
+        system_builder
+            .with_query(<Write<Marker>>::query())
+            .build(|_, world, _, query| {
+                query.iter(world).for_each(|mut marker| marker.0 += 1);
+            })
+    })
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "gameplay::start",
+            "gameplay::pause",
+            "pause::start",
+            "pause::stop",
+            "gameplay::resume",
+            // Popping the last scene on the fifth frame exits before the schedule runs.
+            "gameplay::stop [Marker(4)]",
+        ]
+    );
+}
+
+#[test]
+fn late_update_runs_after_the_schedule() {
+    let observed = Arc::new(Mutex::new(Vec::new()));
+
+    struct LateScene {
+        observed: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Scene for LateScene {
+        fn start(&mut self, context: Context) {
+            context.world.insert((), vec![(Marker(0),)]);
+        }
+
+        fn late_update(&mut self, context: Context) -> Transition {
+            let mut observed = self.observed.lock().unwrap();
+            observed.extend(<Read<Marker>>::query().iter(context.world).map(|m| m.0));
+            Transition::None
+        }
+    }
+
+    ApplicationBuilder::new(
+        LateScene {
+            observed: observed.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(3)))
+    .with_system("advance_markers", |_, system_builder| {
+        system_builder
+            .with_query(<Write<Marker>>::query())
+            .build(|_, world, _, query| {
+                query.iter(world).for_each(|mut marker| marker.0 += 1);
+            })
+    })
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(*observed.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn executes_stages_at_their_point_of_the_frame() {
+    use crius::schedule::ScheduleBuilder;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+    let log: Log = Arc::new(Mutex::new(Vec::new()));
+
+    struct StagedScene {
+        log: Log,
+    }
+
+    impl StagedScene {
+        fn push(&self, entry: &str) {
+            self.log.lock().unwrap().push(entry.to_string());
+        }
+    }
+
+    impl Scene for StagedScene {
+        fn build_schedule<'a>(&mut self, builder: ScheduleBuilder<'a>) -> ScheduleBuilder<'a> {
+            let log = self.log.clone();
+            builder.with_thread_local_fn_in_stage(Stage::Render, move |_| {
+                log.lock().unwrap().push("scene Render".to_string())
+            })
+        }
+
+        fn update(&mut self, _context: Context) -> Transition {
+            self.push("update");
+            Transition::None
+        }
+
+        fn late_update(&mut self, _context: Context) -> Transition {
+            self.push("late_update");
+            Transition::None
+        }
+
+        fn draw(&mut self, _context: Context) {
+            self.push("draw");
+        }
+    }
+
+    let stages = [
+        Stage::Last,
+        Stage::Render,
+        Stage::PreRender,
+        Stage::PostUpdate,
+        Stage::Update,
+        Stage::Custom("physics"),
+        Stage::PreUpdate,
+        Stage::First,
+    ];
+    let mut builder = ApplicationBuilder::new(StagedScene { log: log.clone() }, ".")
+        .with_settings(Settings::default())
+        .headless(unthrottled(Some(1)))
+        .with_stage_before(Stage::Custom("physics"), Stage::Update);
+    for stage in stages {
+        let log = log.clone();
+        builder = builder.with_thread_local_fn_in_stage(stage, move |_| {
+            log.lock().unwrap().push(stage.to_string())
+        });
+    }
+    builder.build().unwrap().run().unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "First",
+            "PreUpdate",
+            "update",
+            "physics",
+            "Update",
+            "PostUpdate",
+            "late_update",
+            "PreRender",
+            "Render",
+            "scene Render",
+            "draw",
+            "Last",
+        ]
+    );
+}
+
+#[test]
+fn rejects_systems_in_stages_that_were_not_added() {
+    use crius::Error;
+
+    let scene = CountingScene {
+        updates: Arc::new(AtomicUsize::new(0)),
+    };
+    let result = ApplicationBuilder::new(scene, ".")
+        .with_settings(Settings::default())
+        .with_thread_local_fn_in_stage(Stage::Custom("physics"), |_| {})
+        .build();
+
+    match result {
+        Err(Error::Stage { stage, .. }) => assert_eq!(stage, "physics"),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("built an application with systems in a missing stage"),
+    }
+}
+
+#[test]
+fn rejects_serializable_components_with_the_same_name() {
+    use crius::Error;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Armor(u32);
+
+    let scene = CountingScene {
+        updates: Arc::new(AtomicUsize::new(0)),
+    };
+    let result = ApplicationBuilder::new(scene, ".")
+        .with_settings(Settings::default())
+        .with_serializable_component::<Health>("health")
+        .with_serializable_component::<Armor>("health")
+        .build();
+
+    match result {
+        Err(Error::Component { component, .. }) => assert_eq!(component, "health"),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("built an application with two components named `health`"),
+    }
+}
+
+#[test]
+fn rejects_checksum_components_that_were_not_registered() {
+    use crius::Error;
+
+    let scene = CountingScene {
+        updates: Arc::new(AtomicUsize::new(0)),
+    };
+    let result = ApplicationBuilder::new(scene, ".")
+        .with_settings(Settings::default())
+        .with_checksum_component("distance")
+        .build();
+
+    match result {
+        Err(Error::Component { component, .. }) => assert_eq!(component, "distance"),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("built an application checksumming an unknown component"),
+    }
+}
+
+#[test]
+fn stops_on_scene_systems_in_stages_that_were_not_added() {
+    use crius::schedule::ScheduleBuilder;
+    use crius::Error;
+
+    struct MenuScene {
+        started: Arc<AtomicUsize>,
+    }
+
+    impl Scene for MenuScene {
+        fn update(&mut self, _context: Context) -> Transition {
+            Transition::Push(Box::new(PhysicsScene {
+                started: self.started.clone(),
+            }))
+        }
+    }
+
+    struct PhysicsScene {
+        started: Arc<AtomicUsize>,
+    }
+
+    impl Scene for PhysicsScene {
+        fn build_schedule<'a>(&mut self, builder: ScheduleBuilder<'a>) -> ScheduleBuilder<'a> {
+            builder.with_thread_local_fn_in_stage(Stage::Custom("physics"), |_| {})
+        }
+
+        fn start(&mut self, _context: Context) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let result = ApplicationBuilder::new(
+        MenuScene {
+            started: started.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(10)))
+    .build()
+    .unwrap()
+    .run();
+
+    match result {
+        Err(Error::Stage { stage, .. }) => assert_eq!(stage, "physics"),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(()) => panic!("ran a scene with systems in a missing stage"),
+    }
+    assert_eq!(started.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn time_is_shared_by_scenes_and_systems() {
+    let scene_frames = Arc::new(Mutex::new(Vec::new()));
+    let system_frames = Arc::new(Mutex::new(Vec::new()));
+    let recorded_frames = system_frames.clone();
+
+    struct TimedScene {
+        frames: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Scene for TimedScene {
+        fn update(&mut self, context: Context) -> Transition {
+            let time = context.time();
+            assert!(time.elapsed() >= time.delta());
+            self.frames.lock().unwrap().push(time.frame_number());
+            Transition::None
+        }
+    }
+
+    ApplicationBuilder::new(
+        TimedScene {
+            frames: scene_frames.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(3)))
+    .with_system("record_frames", move |_, system_builder| {
+        let recorded_frames = recorded_frames.clone();
+        system_builder
+            .read_resource::<Time>()
+            .build(move |_, _, time, _| recorded_frames.lock().unwrap().push(time.frame_number()))
+    })
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(*scene_frames.lock().unwrap(), vec![1, 2, 3]);
+    assert_eq!(*system_frames.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn executes_the_active_scene_schedule() {
+    use crius::schedule::ScheduleBuilder;
+    use std::sync::atomic::AtomicU32;
+
+    struct CountingSchedule {
+        name: &'static str,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        simulate_when_paused: bool,
+        push_on_frame: Option<u32>,
+        frames: Arc<AtomicU32>,
+    }
+
+    impl Scene for CountingSchedule {
+        fn build_schedule<'a>(&mut self, builder: ScheduleBuilder<'a>) -> ScheduleBuilder<'a> {
+            let name = self.name;
+            let log = self.log.clone();
+            builder.with_thread_local_fn(move |_| log.lock().unwrap().push(name))
+        }
+
+        fn start(&mut self, mut context: Context) {
+            context.set_simulate_when_paused(self.simulate_when_paused);
+        }
+
+        fn update(&mut self, _context: Context) -> Transition {
+            let frame = self.frames.fetch_add(1, Ordering::SeqCst) + 1;
+            match self.push_on_frame {
+                Some(push_on_frame) if push_on_frame == frame => {
+                    Transition::Push(Box::new(CountingSchedule {
+                        name: "overlay",
+                        log: self.log.clone(),
+                        simulate_when_paused: false,
+                        push_on_frame: None,
+                        frames: self.frames.clone(),
+                    }))
+                }
+                _ => Transition::None,
+            }
+        }
+    }
+
+    for &simulate_when_paused in [false, true].iter() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let global_log = log.clone();
+
+        ApplicationBuilder::new(
+            CountingSchedule {
+                name: "gameplay",
+                log: log.clone(),
+                simulate_when_paused,
+                push_on_frame: Some(2),
+                frames: Arc::new(AtomicU32::new(0)),
+            },
+            ".",
+        )
+        .with_settings(Settings::default())
+        .headless(unthrottled(Some(3)))
+        .with_thread_local_fn(move |_| global_log.lock().unwrap().push("global"))
+        .build()
+        .unwrap()
+        .run()
+        .unwrap();
+
+        let expected = if simulate_when_paused {
+            vec![
+                "global", "gameplay", "global", "gameplay", "overlay", "global", "gameplay",
+                "overlay",
+            ]
+        } else {
+            vec![
+                "global", "gameplay", "global", "overlay", "global", "overlay",
+            ]
+        };
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+}
+
+#[test]
+fn instantiates_prefabs_from_the_assets_path() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    struct LevelScene {
+        healths: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Scene for LevelScene {
+        fn start(&mut self, mut context: Context) {
+            context.instantiate_prefab("level.yml").unwrap();
+        }
+
+        fn update(&mut self, context: Context) -> Transition {
+            let mut healths = self.healths.lock().unwrap();
+            healths.extend(<Read<Health>>::query().iter(context.world).map(|h| h.0));
+            Transition::Quit
+        }
+    }
+
+    let assets_path = std::env::temp_dir().join(format!("crius-assets-{}", std::process::id()));
+    std::fs::create_dir_all(&assets_path).unwrap();
+    std::fs::write(
+        assets_path.join("level.yml"),
+        "entities:\n  - components:\n      health: 3\n",
+    )
+    .unwrap();
+
+    let healths = Arc::new(Mutex::new(Vec::new()));
+    ApplicationBuilder::new(
+        LevelScene {
+            healths: healths.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings {
+        assets_path: assets_path.to_string_lossy().into_owned(),
+        ..Settings::default()
+    })
+    .headless(unthrottled(None))
+    .with_serializable_component::<Health>("health")
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+    std::fs::remove_dir_all(&assets_path).unwrap();
+
+    assert_eq!(*healths.lock().unwrap(), vec![3]);
+}
+
+#[test]
+fn reports_changes_under_the_assets_path() {
+    use crius::application::settings::WatchSettings;
+    use crius::ecs::event::ListenerId;
+    use crius::watcher::{FileChangeKind, FileChanged};
+    use std::path::PathBuf;
+
+    struct WatchingScene {
+        assets_path: PathBuf,
+        listener: Option<ListenerId>,
+        frames: u32,
+        changes: Arc<Mutex<Vec<FileChanged>>>,
+    }
+
+    impl Scene for WatchingScene {
+        fn start(&mut self, context: Context) {
+            let mut channel = context
+                .world
+                .resources
+                .get_mut::<EventChannel<FileChanged>>()
+                .unwrap();
+            self.listener = Some(channel.bind_listener(16));
+        }
+
+        fn update(&mut self, context: Context) -> Transition {
+            self.frames += 1;
+            if self.frames == 1 {
+                std::fs::write(self.assets_path.join("level.yml"), "entities: []\n").unwrap();
+            }
+
+            let channel = context
+                .world
+                .resources
+                .get::<EventChannel<FileChanged>>()
+                .unwrap();
+            let mut changes = self.changes.lock().unwrap();
+            while let Some(change) = channel.read(self.listener.unwrap()) {
+                changes.push(change);
+            }
+
+            if changes.is_empty() {
+                Transition::None
+            } else {
+                Transition::Quit
+            }
+        }
+    }
+
+    let assets_path =
+        std::env::temp_dir().join(format!("crius-watched-assets-{}", std::process::id()));
+    std::fs::create_dir_all(&assets_path).unwrap();
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    ApplicationBuilder::new(
+        WatchingScene {
+            assets_path: assets_path.clone(),
+            listener: None,
+            frames: 0,
+            changes: changes.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings {
+        assets_path: assets_path.to_string_lossy().into_owned(),
+        watch: WatchSettings {
+            enabled: true,
+            poll_interval: 0.0,
+        },
+        ..Settings::default()
+    })
+    .headless(unthrottled(Some(10)))
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+    std::fs::remove_dir_all(&assets_path).unwrap();
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![FileChanged {
+            path: PathBuf::from("level.yml"),
+            kind: FileChangeKind::Created,
+        }]
+    );
+}
+
+#[test]
+fn reports_settings_that_fail_to_reload() {
+    use crius::ecs::event::ListenerId;
+    use crius::watcher::SettingsError;
+    use std::path::PathBuf;
+
+    struct ReloadingScene {
+        settings_path: PathBuf,
+        listener: Option<ListenerId>,
+        frames: u32,
+        errors: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Scene for ReloadingScene {
+        fn start(&mut self, context: Context) {
+            let mut channel = context
+                .world
+                .resources
+                .get_mut::<EventChannel<SettingsError>>()
+                .unwrap();
+            self.listener = Some(channel.bind_listener(16));
+        }
+
+        fn update(&mut self, context: Context) -> Transition {
+            self.frames += 1;
+            if self.frames == 1 {
+                std::fs::write(&self.settings_path, "watch: [\n").unwrap();
+            }
+
+            let channel = context
+                .world
+                .resources
+                .get::<EventChannel<SettingsError>>()
+                .unwrap();
+            let mut errors = self.errors.lock().unwrap();
+            while let Some(error) = channel.read(self.listener.unwrap()) {
+                errors.push(error.error.to_string());
+            }
+
+            if errors.is_empty() {
+                Transition::None
+            } else {
+                Transition::Quit
+            }
+        }
+    }
+
+    let directory =
+        std::env::temp_dir().join(format!("crius-reloaded-settings-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("assets")).unwrap();
+    let settings_path = directory.join("settings.yml");
+    std::fs::write(
+        &settings_path,
+        "watch:\n  enabled: true\n  poll_interval: 0.0\n",
+    )
+    .unwrap();
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    ApplicationBuilder::new(
+        ReloadingScene {
+            settings_path,
+            listener: None,
+            frames: 0,
+            errors: errors.clone(),
+        },
+        &directory,
+    )
+    .headless(unthrottled(Some(10)))
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("settings.yml"), "{}", errors[0]);
+}
+
+#[test]
+fn hands_gamepad_events_to_the_state_and_the_scenes() {
+    use crius::input::{GamepadButton, GamepadEventKind, GamepadState, VirtualGamepads};
+
+    struct GamepadScene {
+        gamepads: VirtualGamepads,
+        events: Arc<Mutex<Vec<GamepadEventKind>>>,
+    }
+
+    impl Scene for GamepadScene {
+        fn start(&mut self, _context: Context) {
+            let id = self.gamepads.connect("Pad");
+            self.gamepads.press(id, GamepadButton::Start);
+        }
+
+        fn handle_event(&mut self, _context: Context, event: Event) -> Transition {
+            if let Event::Gamepad(event) = event {
+                self.events.lock().unwrap().push(event.kind);
+            }
+            Transition::None
+        }
+
+        fn update(&mut self, context: Context) -> Transition {
+            let gamepads = context.world.resources.get::<GamepadState>().unwrap();
+            let pressed = gamepads
+                .connected()
+                .filter_map(|id| gamepads.gamepad(id))
+                .any(|gamepad| gamepad.is_button_pressed(GamepadButton::Start));
+            if pressed {
+                Transition::Quit
+            } else {
+                Transition::None
+            }
+        }
+    }
+
+    let gamepads = VirtualGamepads::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    ApplicationBuilder::new(
+        GamepadScene {
+            gamepads: gamepads.clone(),
+            events: events.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .with_gamepad_backend(gamepads)
+    .headless(unthrottled(Some(10)))
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            GamepadEventKind::Connected {
+                name: "Pad".to_string()
+            },
+            GamepadEventKind::ButtonPressed(GamepadButton::Start),
+        ]
+    );
+}
+
+#[test]
+fn replays_recordings_and_detects_divergence() {
+    use crius::input::{GamepadButton, GamepadState, VirtualGamepads};
+    use crius::replay::Replay;
+    use crius::Error;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Distance(f64);
+
+    struct DrivingScene {
+        // Only set while recording, replays get the gamepad events from the recording.
+        gamepads: Option<VirtualGamepads>,
+    }
+
+    impl Scene for DrivingScene {
+        fn start(&mut self, context: Context) {
+            context.world.insert((), vec![(Distance(0.0),)]);
+            if let Some(gamepads) = &self.gamepads {
+                let id = gamepads.connect("Pad");
+                gamepads.press(id, GamepadButton::RightTrigger);
+            }
+        }
+
+        fn update(&mut self, context: Context) -> Transition {
+            let delta = context.world.resources.get::<Time>().unwrap().delta();
+            let driving = {
+                let gamepads = context.world.resources.get::<GamepadState>().unwrap();
+                let driving = gamepads
+                    .connected()
+                    .filter_map(|id| gamepads.gamepad(id))
+                    .any(|gamepad| gamepad.is_button_held(GamepadButton::RightTrigger));
+                driving
+            };
+            if driving {
+                for mut distance in <Write<Distance>>::query().iter(context.world) {
+                    distance.0 += delta.as_secs_f64();
+                }
+            }
+            Transition::None
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("crius-recording-{}.yml", std::process::id()));
+    let application = |scene: DrivingScene| {
+        ApplicationBuilder::new(scene, ".")
+            .with_settings(Settings::default())
+            .with_serializable_component::<Distance>("distance")
+            .with_checksum_component("distance")
+    };
+
+    let gamepads = VirtualGamepads::new();
+    application(DrivingScene {
+        gamepads: Some(gamepads.clone()),
+    })
+    .with_gamepad_backend(gamepads)
+    .headless(unthrottled(Some(5)))
+    .record(&path)
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    let replayed = application(DrivingScene { gamepads: None })
+        .replay(&path)
+        .build()
+        .unwrap()
+        .run();
+    assert!(replayed.is_ok(), "{:?}", replayed);
+
+    let mut replay = Replay::load(&path).unwrap();
+    assert_eq!(replay.frames.len(), 5);
+    replay.frames[3].delta += std::time::Duration::from_secs(1);
+    replay.save(&path).unwrap();
+
+    let diverged = application(DrivingScene { gamepads: None })
+        .replay(&path)
+        .build()
+        .unwrap()
+        .run();
+    std::fs::remove_file(&path).unwrap();
+
+    match diverged {
+        Err(Error::Diverged { frame, .. }) => assert_eq!(frame, 3),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn delivers_user_events_sent_from_other_threads() {
+    #[derive(Debug, PartialEq)]
+    struct Loaded(u32);
+
+    struct WaitingScene {
+        received: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Scene for WaitingScene {
+        fn handle_event(&mut self, _context: Context, event: Event) -> Transition {
+            if let Event::User(event) = event {
+                assert!(event.type_name().ends_with("::Loaded"));
+                let Loaded(value) = event.downcast::<Loaded>().unwrap();
+                self.received.lock().unwrap().push(value);
+                if value == 2 {
+                    return Transition::Quit;
+                }
+            }
+            Transition::None
+        }
+    }
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let builder = ApplicationBuilder::new(
+        WaitingScene {
+            received: received.clone(),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(HeadlessSettings {
+        tick_rate: Some(1000),
+        max_frames: Some(10_000),
+    });
+
+    let sender = builder.event_sender();
+    sender.send(Loaded(1)).unwrap();
+    let thread_sender = sender.clone();
+    let thread = std::thread::spawn(move || thread_sender.send(Loaded(2)).unwrap());
+
+    builder.build().unwrap().run().unwrap();
+    thread.join().unwrap();
+
+    assert_eq!(*received.lock().unwrap(), vec![1, 2]);
+    assert!(sender.send(Loaded(3)).is_err());
+}
+
+#[test]
+fn runs_event_writers_before_their_readers() {
+    struct Ping(u64);
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let reader_received = received.clone();
+
+    ApplicationBuilder::new(
+        CountingScene {
+            updates: Arc::new(AtomicUsize::new(0)),
+        },
+        ".",
+    )
+    .with_settings(Settings::default())
+    .headless(unthrottled(Some(3)))
+    .with_event::<Ping>()
+    .with_system("reader", move |_, system_builder| {
+        let (system_builder, mut pings) = system_builder.read_events::<Ping>();
+        let received = reader_received.clone();
+        system_builder
+            .read_resource::<Time>()
+            .build(move |_, _, (events, time), _| {
+                let frame = time.frame_number();
+                let mut received = received.lock().unwrap();
+                received.extend(pings.read(events).map(|ping| (frame, ping.0)));
+            })
+    })
+    .with_system("writer", |_, system_builder| {
+        system_builder
+            .write_resource::<Events<Ping>>()
+            .read_resource::<Time>()
+            .build(|_, _, (events, time), _| events.send(Ping(time.frame_number())))
+    })
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+
+    assert_eq!(*received.lock().unwrap(), vec![(1, 1), (2, 2), (3, 3)]);
+}
+
+#[test]
+fn builds_plugins_after_their_dependencies() {
+    use crius::Error;
+
+    type BuildLog = Arc<Mutex<Vec<&'static str>>>;
+
+    struct Audio(BuildLog);
+    struct Mixer(BuildLog);
+    struct Reverb(BuildLog);
+    struct Bundle(BuildLog);
+
+    impl Plugin for Bundle {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            self.0.lock().unwrap().push("bundle");
+            builder.with_plugin(Audio(self.0.clone()))
+        }
+    }
+
+    impl Plugin for Audio {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            self.0.lock().unwrap().push("audio");
+            builder.with_plugin(Reverb(self.0.clone()))
+        }
+    }
+
+    impl Plugin for Mixer {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            self.0.lock().unwrap().push("mixer");
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Audio>()]
+        }
+    }
+
+    impl Plugin for Reverb {
+        fn build(&self, builder: ApplicationBuilder) -> ApplicationBuilder {
+            self.0.lock().unwrap().push("reverb");
+            builder
+        }
+
+        fn dependencies(&self) -> Vec<Dependency> {
+            vec![Dependency::on::<Mixer>()]
+        }
+    }
+
+    let builder = || {
+        ApplicationBuilder::new(
+            CountingScene {
+                updates: Arc::new(AtomicUsize::new(0)),
+            },
+            ".",
+        )
+        .with_settings(Settings::default())
+    };
+    let log = BuildLog::default();
+
+    builder()
+        .with_plugin(Mixer(log.clone()))
+        .with_plugin(Audio(log.clone()))
+        .build()
+        .unwrap();
+    assert_eq!(*log.lock().unwrap(), vec!["audio", "mixer", "reverb"]);
+
+    log.lock().unwrap().clear();
+    builder()
+        .with_plugin(Mixer(log.clone()))
+        .with_plugin(Bundle(log.clone()))
+        .build()
+        .unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["bundle", "audio", "mixer", "reverb"]
+    );
+
+    let duplicate = builder()
+        .with_plugin(Audio(log.clone()))
+        .with_plugin(Audio(log.clone()))
+        .build();
+    match duplicate {
+        Err(Error::Plugin { message, .. }) => assert!(message.contains("more than once")),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("built an application with a duplicate plugin"),
+    }
+
+    match builder().with_plugin(Mixer(log)).build() {
+        Err(Error::Plugin { message, .. }) => assert!(message.contains("not added")),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("built an application with a missing dependency"),
+    }
+}
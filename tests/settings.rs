@@ -0,0 +1,165 @@
+use crius::application::settings::{FullscreenMode, MonitorSelector, Settings, SettingsLoader};
+use crius::input::Bindings;
+use crius::prelude::*;
+use crius::Error;
+use std::fs;
+use std::path::PathBuf;
+
+struct EmptyScene;
+
+impl Scene for EmptyScene {}
+
+fn scratch_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("crius-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn loads_the_playground_settings() {
+    let settings = Settings::load("examples/playground/settings.yml").unwrap();
+
+    assert_eq!(settings.window.unwrap().title, "Playground Application");
+}
+
+#[test]
+fn loads_the_playground_bindings() {
+    let bindings = Bindings::load("examples/playground/bindings.yml").unwrap();
+
+    assert_eq!(bindings.actions["foo"].len(), 2);
+}
+
+#[test]
+fn build_reports_missing_settings_file() {
+    let directory = scratch_directory("missing");
+
+    match ApplicationBuilder::new(EmptyScene, &directory).build() {
+        Err(Error::Io { path, .. }) => assert_eq!(path, directory.join("settings.yml")),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("build succeeded without a settings file"),
+    }
+}
+
+#[test]
+fn build_reports_parse_error_location() {
+    let directory = scratch_directory("malformed");
+    fs::write(
+        directory.join("settings.yml"),
+        "version:\n  major: 0\n  minor: one\n  patch: 0\nassets_path: \"./assets\"\n",
+    )
+    .unwrap();
+
+    match ApplicationBuilder::new(EmptyScene, &directory).build() {
+        Err(Error::Parse { line, .. }) => assert_eq!(line, Some(3)),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("build succeeded with malformed settings"),
+    }
+}
+
+#[test]
+fn build_reports_invalid_window_sizes() {
+    let directory = scratch_directory("invalid");
+    fs::write(
+        directory.join("settings.yml"),
+        r#"---
+version:
+  major: 0
+  minor: 1
+  patch: 0
+assets_path: "./assets"
+window:
+  title: "Invalid"
+  size:
+    width: 1024
+    height: 768
+  min_size:
+    width: 2048
+    height: 768
+  max_size: null
+  resizeable: true
+  maximized: false
+  visible: true
+  transparent: false
+  decorations: true
+  always_on_top: false
+"#,
+    )
+    .unwrap();
+
+    match ApplicationBuilder::new(EmptyScene, &directory).build() {
+        Err(Error::Validation { field, .. }) => assert_eq!(field, "window.min_size"),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("build succeeded with invalid settings"),
+    }
+}
+
+#[test]
+fn layers_user_settings_and_arguments_over_project_settings() {
+    let directory = scratch_directory("layered");
+    fs::write(
+        directory.join("settings.toml"),
+        "assets_path = \"./data\"\n\n[window]\ntitle = \"Project\"\ndecorations = false\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("user.json"),
+        r#"{"window": {"title": "User", "always_on_top": true}}"#,
+    )
+    .unwrap();
+
+    let settings = SettingsLoader::new()
+        .with_file(directory.join("settings.toml"))
+        .with_optional_file(directory.join("user.json"))
+        .with_optional_file(directory.join("missing.ron"))
+        .with_args(vec!["game", "--set", "window.title=Command Line"])
+        .load()
+        .unwrap();
+
+    assert_eq!(settings.assets_path, "./data");
+    let window = settings.window.unwrap();
+    assert_eq!(window.title, "Command Line");
+    assert!(!window.decorations);
+    assert!(window.always_on_top);
+    assert!(window.resizeable);
+}
+
+#[test]
+fn loads_fullscreen_settings_with_monitors_by_index_or_name() {
+    let directory = scratch_directory("fullscreen");
+    fs::write(
+        directory.join("settings.yml"),
+        r#"---
+window:
+  fullscreen:
+    mode: exclusive
+    monitor: 1
+    video_mode:
+      size:
+        width: 1920
+        height: 1080
+  position:
+    x: 100
+    y: -20
+windows:
+  - name: inspector
+    fullscreen:
+      mode: borderless
+      monitor: "DP-2"
+"#,
+    )
+    .unwrap();
+
+    let settings = Settings::load(directory.join("settings.yml")).unwrap();
+
+    let window = settings.window.unwrap();
+    assert_eq!(window.fullscreen.mode, FullscreenMode::Exclusive);
+    assert_eq!(window.fullscreen.monitor, Some(MonitorSelector::Index(1)));
+    assert_eq!(window.fullscreen.video_mode.unwrap().refresh_rate, None);
+    assert_eq!(window.position.unwrap().y, -20);
+    let inspector = &settings.windows[0];
+    assert_eq!(inspector.name, "inspector");
+    assert_eq!(
+        inspector.fullscreen.monitor,
+        Some(MonitorSelector::Name("DP-2".to_string()))
+    );
+}